@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::mem;
+use std::sync::Arc;
 
 use abci::*;
 use log::{info, warn};
@@ -11,7 +12,7 @@ use serde::{Deserialize, Serialize};
 #[cfg(all(not(feature = "mock-enclave"), target_os = "linux"))]
 use crate::enclave_bridge::real::start_zmq;
 use crate::enclave_bridge::EnclaveProxy;
-use crate::staking::StakingTable;
+use crate::staking::{StakingConfig, StakingMetrics, StakingTable};
 use chain_core::common::MerkleTree;
 use chain_core::common::Timespec;
 use chain_core::common::{H256, HASH_SIZE_256};
@@ -151,6 +152,11 @@ pub struct ChainNodeApp<T: EnclaveProxy> {
     pub kv_buffer: KVBuffer,
     /// mempool buffer of key-value storage
     pub mempool_kv_buffer: KVBuffer,
+
+    /// policies of the staking table, applied after restored from storage or init chain
+    pub staking_config: StakingConfig,
+    /// metrics hook of the consensus staking table, installed after restored from storage or init chain
+    pub staking_metrics: Option<Arc<dyn StakingMetrics>>,
 }
 
 pub fn get_validator_key(node: &CouncilNodeMeta) -> PubKey {
@@ -283,6 +289,9 @@ impl<T: EnclaveProxy> ChainNodeApp<T> {
             mempool_staking_buffer: HashMap::new(),
             kv_buffer: HashMap::new(),
             mempool_kv_buffer: HashMap::new(),
+
+            staking_config: StakingConfig::default(),
+            staking_metrics: None,
         }
     }
 
@@ -355,6 +364,7 @@ impl<T: EnclaveProxy> ChainNodeApp<T> {
                     .top_level
                     .network_params
                     .get_required_council_node_stake(),
+                last_state.top_level.network_params.get_max_validators(),
             );
             ChainNodeApp::restore_from_storage(
                 tx_validator,
@@ -392,10 +402,39 @@ impl<T: EnclaveProxy> ChainNodeApp<T> {
                 mempool_staking_buffer: HashMap::new(),
                 kv_buffer: HashMap::new(),
                 mempool_kv_buffer: HashMap::new(),
+
+                staking_config: StakingConfig::default(),
+                staking_metrics: None,
             }
         }
     }
 
+    /// Set the policies of the staking table, they are applied to the restored state right away,
+    /// or to the genesis state in init chain.
+    pub fn with_staking_config(mut self, config: StakingConfig) -> Self {
+        for state in self
+            .last_state
+            .iter_mut()
+            .chain(self.mempool_state.iter_mut())
+        {
+            state.staking_table.configure(&config);
+        }
+        self.staking_config = config;
+        self
+    }
+
+    /// Set the metrics hook of the staking table, it's installed on the restored consensus state
+    /// right away, or on the genesis state in init chain.
+    /// The mempool state is a clone of the consensus state, which doesn't keep the hook, so the
+    /// operations are only reported once when committed.
+    pub fn with_staking_metrics(mut self, metrics: Arc<dyn StakingMetrics>) -> Self {
+        if let Some(state) = self.last_state.as_mut() {
+            state.staking_table.set_metrics(metrics.clone());
+        }
+        self.staking_metrics = Some(metrics);
+        self
+    }
+
     /// Handles InitChain requests:
     /// should validate initial genesis distribution, initialize everything in the key-value DB and check it matches the expected values
     /// provided as arguments.
@@ -467,12 +506,18 @@ impl<T: EnclaveProxy> ChainNodeApp<T> {
             .iter()
             .map(|(addr, _)| *addr)
             .collect::<Vec<_>>();
-        let staking_table = StakingTable::from_genesis(
+        let mut staking_table = StakingTable::from_genesis(
             &staking_getter!(self, 0),
             network_params.get_required_council_node_stake(),
             network_params.get_max_validators(),
             &val_addresses,
         );
+        // the genesis validators are chosen with the default voting power unit, which is the one
+        // of `req.validators`, the configured unit takes effect in the first end block
+        staking_table.configure(&self.staking_config);
+        if let Some(metrics) = &self.staking_metrics {
+            staking_table.set_metrics(metrics.clone());
+        }
 
         let genesis_state = ChainNodeState::genesis(
             genesis_app_hash,
@@ -70,6 +70,11 @@ fn process_txs(delivered_txs: &[TxAux], db: &mut impl StoreKV) {
                 // staked state updated in deliver_tx
                 // validator state updated in end_block
             }
+            TxAux::PublicTx(TxPublicAux::EditValidatorTx(tx, witness)) => {
+                chain_storage::store_tx_body(db, &txid, &tx.encode());
+                chain_storage::store_tx_witness(db, &txid, &witness.encode());
+                // validator metadata updated in deliver_tx
+            }
         }
     }
 }
@@ -405,13 +405,14 @@ fn generate_tx_staking_change_event(tx_action: TxAction) -> Option<abci::Event>
                 ..
             } => Some(StakingEvent::Unbond(&unbond.0, unbond.1, unbonded_from, fee).into()),
             TxPublicAction::NodeJoin {
-                address,
-                council_node,
-                ..
-            } => Some(StakingEvent::NodeJoin(&address, council_node).into()),
+                address, node_meta, ..
+            } => Some(StakingEvent::NodeJoin(&address, node_meta).into()),
             TxPublicAction::Unjail(staking_address) => {
                 Some(StakingEvent::Unjail(&staking_address).into())
             }
+            TxPublicAction::EditValidator(staking_address) => {
+                Some(StakingEvent::EditValidator(&staking_address).into())
+            }
         },
     }
 }
@@ -6,18 +6,19 @@ use abci::Pair as KVPair;
 use abci::*;
 use chain_core::common::{TendermintEventKey, TendermintEventType, Timespec};
 use chain_core::init::coin::Coin;
-use chain_core::state::account::{CouncilNodeMeta, PunishmentKind, StakedStateAddress};
+use chain_core::state::account::{NodeMetadata, PunishmentKind, StakedStateAddress};
 use chain_core::tx::fee::Fee;
 
 pub(crate) enum StakingEvent<'a> {
     Deposit(&'a StakedStateAddress, Coin),
     Unbond(&'a StakedStateAddress, Coin, Timespec, Fee),
     Withdraw(&'a StakedStateAddress, Coin),
-    NodeJoin(&'a StakedStateAddress, CouncilNodeMeta),
+    NodeJoin(&'a StakedStateAddress, NodeMetadata),
     Reward(&'a StakedStateAddress, Coin),
     Jail(&'a StakedStateAddress, Timespec, PunishmentKind),
     Slash(&'a StakedStateAddress, Coin, Coin, PunishmentKind),
     Unjail(&'a StakedStateAddress),
+    EditValidator(&'a StakedStateAddress),
 }
 
 impl<'a> From<StakingEvent<'a>> for Event {
@@ -34,8 +35,8 @@ impl<'a> From<StakingEvent<'a>> for Event {
             StakingEvent::Withdraw(staking_address, withdraw_amount) => {
                 builder.withdraw(staking_address, withdraw_amount)
             }
-            StakingEvent::NodeJoin(staking_address, council_node) => {
-                builder.node_join(staking_address, council_node)
+            StakingEvent::NodeJoin(staking_address, node_meta) => {
+                builder.node_join(staking_address, node_meta)
             }
             StakingEvent::Reward(staking_address, reward_amount) => {
                 builder.reward(staking_address, reward_amount)
@@ -55,6 +56,7 @@ impl<'a> From<StakingEvent<'a>> for Event {
                 punishment_kind,
             ),
             StakingEvent::Unjail(staking_address) => builder.unjail(staking_address),
+            StakingEvent::EditValidator(staking_address) => builder.edit_validator(staking_address),
         }
 
         builder.to_event()
@@ -119,7 +121,7 @@ impl StakingEventBuilder {
         );
     }
 
-    fn node_join(&mut self, staking_address: &StakedStateAddress, node: CouncilNodeMeta) {
+    fn node_join(&mut self, staking_address: &StakedStateAddress, node: NodeMetadata) {
         self.attributes
             .push(staking_address_attribute(staking_address));
         self.attributes.push(StakingEventOpType::NodeJoin.into());
@@ -188,6 +190,13 @@ impl StakingEventBuilder {
         self.attributes.push(StakingEventOpType::Unjail.into());
     }
 
+    fn edit_validator(&mut self, staking_address: &StakedStateAddress) {
+        self.attributes
+            .push(staking_address_attribute(staking_address));
+        self.attributes
+            .push(StakingEventOpType::EditValidator.into());
+    }
+
     fn to_event(&self) -> Event {
         let mut event = Event::new();
         event.field_type = TendermintEventType::StakingChange.to_string();
@@ -225,6 +234,7 @@ enum StakingEventOpType {
     Jail,
     Slash,
     Unjail,
+    EditValidator,
 }
 
 impl fmt::Display for StakingEventOpType {
@@ -238,6 +248,7 @@ impl fmt::Display for StakingEventOpType {
             StakingEventOpType::Jail => write!(f, "jail"),
             StakingEventOpType::Slash => write!(f, "slash"),
             StakingEventOpType::Unjail => write!(f, "unjail"),
+            StakingEventOpType::EditValidator => write!(f, "editvalidator"),
         }
     }
 }
@@ -280,7 +291,7 @@ enum StakingDiff {
     Bonded(StakingCoinChange, Coin),
     Unbonded(StakingCoinChange, Coin),
     UnbondedFrom(Timespec),
-    NodeJoin(CouncilNodeMeta),
+    NodeJoin(NodeMetadata),
     JailedUntil(Timespec),
 }
 
@@ -314,12 +325,18 @@ impl Serialize for StakingDiff {
                 state.serialize_field("value", &unbonded_from)?;
                 state.end()
             }
-            StakingDiff::NodeJoin(node) => {
+            StakingDiff::NodeJoin(NodeMetadata::CouncilNode(node)) => {
                 let mut state = serializer.serialize_struct("NodeJoin", 2)?;
                 state.serialize_field("key", "CouncilNode")?;
                 state.serialize_field("value", node)?;
                 state.end()
             }
+            StakingDiff::NodeJoin(NodeMetadata::CommunityNode(node)) => {
+                let mut state = serializer.serialize_struct("NodeJoin", 2)?;
+                state.serialize_field("key", "CommunityNode")?;
+                state.serialize_field("value", node)?;
+                state.end()
+            }
             StakingDiff::JailedUntil(jailed_until) => {
                 let mut state = serializer.serialize_struct("JailedUntil", 2)?;
                 state.serialize_field("key", "JailedUntil")?;
@@ -357,7 +374,7 @@ impl fmt::Display for StakingCoinChange {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chain_core::state::account::ConfidentialInit;
+    use chain_core::state::account::{ConfidentialInit, CouncilNodeMeta, NodeCommonInfo};
     use chain_core::state::tendermint::TendermintValidatorPubKey;
     use chain_core::tx::fee::Fee;
     use std::str::FromStr;
@@ -433,7 +450,8 @@ mod tests {
             #[test]
             fn to_string_should_serialize_to_json() {
                 let any_council_node = any_council_node();
-                let staking_diff = StakingDiff::NodeJoin(any_council_node);
+                let staking_diff =
+                    StakingDiff::NodeJoin(NodeMetadata::CouncilNode(any_council_node));
 
                 assert_eq!(
                     staking_diff.to_string(),
@@ -441,6 +459,24 @@ mod tests {
                 );
             }
 
+            #[test]
+            fn community_node_to_string_should_serialize_to_json() {
+                let any_community_node = NodeCommonInfo {
+                    name: String::from("Community Node"),
+                    security_contact: None,
+                    confidential_init: ConfidentialInit {
+                        keypackage: [0u8; 32].to_vec(),
+                    },
+                };
+                let staking_diff =
+                    StakingDiff::NodeJoin(NodeMetadata::CommunityNode(any_community_node));
+
+                assert_eq!(
+                    staking_diff.to_string(),
+                    "{\"key\":\"CommunityNode\",\"value\":{\"name\":\"Community Node\",\"security_contact\":null,\"confidential_init\":{\"keypackage\":\"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=\"}}}",
+                );
+            }
+
             fn any_council_node() -> CouncilNodeMeta {
                 let any_name = String::from("Council Node");
                 let any_security_contact = Some(String::from("security@crypto.com"));
@@ -535,8 +571,11 @@ mod tests {
                 let any_staking_address = any_staking_address();
                 let any_council_node = any_council_node();
 
-                let event: Event =
-                    StakingEvent::NodeJoin(&any_staking_address, any_council_node.clone()).into();
+                let event: Event = StakingEvent::NodeJoin(
+                    &any_staking_address,
+                    NodeMetadata::CouncilNode(any_council_node.clone()),
+                )
+                .into();
 
                 assert_node_join_event(event, &any_staking_address, any_council_node)
             }
@@ -629,6 +668,33 @@ mod tests {
             }
         }
 
+        mod edit_validator {
+            use super::*;
+
+            #[test]
+            fn should_create_edit_validator_event() {
+                let any_staking_address = any_staking_address();
+
+                let event: Event = StakingEvent::EditValidator(&any_staking_address).into();
+
+                assert_eq!(
+                    event.field_type,
+                    TendermintEventType::StakingChange.to_string()
+                );
+                assert_eq!(event.attributes.len(), 2);
+                assert_kv_pair(
+                    event.attributes.first().unwrap(),
+                    TendermintEventKey::StakingAddress.to_string(),
+                    any_staking_address.to_string(),
+                );
+                assert_kv_pair(
+                    event.attributes.get(1).unwrap(),
+                    TendermintEventKey::StakingOpType.to_string(),
+                    StakingEventOpType::EditValidator.to_string(),
+                );
+            }
+        }
+
         fn assert_deposit_event(
             event: Event,
             staking_address: StakedStateAddress,
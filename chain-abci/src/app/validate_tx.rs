@@ -1,5 +1,6 @@
 use super::{BufferType, ChainNodeApp, ChainNodeState};
 use crate::enclave_bridge::EnclaveProxy;
+use crate::staking::BlockContext;
 use crate::storage::{
     process_public_tx, verify_enclave_tx, TxAction, TxEnclaveAction, TxPublicAction,
 };
@@ -163,7 +164,7 @@ fn execute_enclave_tx(
             chain_storage::spend_utxos(kvdb, &spend_utxo);
             state
                 .staking_table
-                .deposit(trie, address, *amount)
+                .deposit(trie, state.block_time, address, *amount)
                 .expect("deposit sanity check");
         }
         TxEnclaveAction::Withdraw {
@@ -177,9 +178,11 @@ fn execute_enclave_tx(
 
             // no panic: tx is already verified, all the error in execution is not allowed.
             // operations are sequential in the state machine, so no concurrent updates
+            let ctx =
+                BlockContext::new(state.block_time, state.block_height, state.max_evidence_age);
             state
                 .staking_table
-                .withdraw(trie, state.block_time, address, *amount)
+                .withdraw(trie, &ctx, address, *amount)
                 .expect("withdraw sanity check");
         }
     }
@@ -104,4 +104,19 @@ mod tests {
         assert!(tracker.is_live(3));
         assert!(!tracker.is_live(2));
     }
+
+    #[test]
+    fn check_liveness_tracker_recovers() {
+        let mut tracker = LivenessTracker::new();
+        for height in 1u64..=3 {
+            tracker.update(5, height.into(), false);
+        }
+        assert!(!tracker.is_live(3));
+
+        // the missed blocks slide out of the window
+        for height in 6u64..=8 {
+            tracker.update(5, height.into(), true);
+        }
+        assert!(tracker.is_live(3));
+    }
 }
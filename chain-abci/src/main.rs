@@ -8,6 +8,7 @@ use chain_abci::app::{sanity_check_enabled, ChainNodeApp};
 use chain_abci::enclave_bridge::mock::MockClient;
 #[cfg(all(not(feature = "mock-enclave"), target_os = "linux"))]
 use chain_abci::enclave_bridge::real::TxValidationApp;
+use chain_abci::staking::StakingConfig;
 use chain_core::init::network::{get_network, get_network_id, init_chain_id};
 use chain_storage::{Storage, StorageConfig, StorageType};
 use log::warn;
@@ -23,6 +24,9 @@ pub struct Config {
     chain_id: Option<String>,
     enclave_server: Option<String>,
     tx_query: Option<String>,
+    // policies of the staking table, must be the same on all the nodes
+    #[serde(default)]
+    staking: StakingConfig,
 }
 
 impl Default for Config {
@@ -34,6 +38,7 @@ impl Default for Config {
             chain_id: None,
             enclave_server: None,
             tx_query: None,
+            staking: StakingConfig::default(),
         }
     }
 }
@@ -189,6 +194,7 @@ fn main() {
             storage,
             config.tx_query,
             config.enclave_server,
-        ),
+        )
+        .with_staking_config(config.staking),
     );
 }
@@ -0,0 +1,66 @@
+//! Policies of the staking table which are not part of the stored state,
+//! loaded from the chain-abci config on every startup.
+use serde::Deserialize;
+
+use chain_core::common::Timespec;
+use chain_core::init::coin::Coin;
+
+use super::table::{
+    FeeDestination, StakingTable, DEFAULT_IMMEDIATE_UNBOND_PENALTY_DIVISOR,
+    DEFAULT_MAX_USED_VALIDATOR_ADDR, DEFAULT_VOTING_POWER_UNIT,
+};
+
+/// Staking policies, see the `with_*` methods of `StakingTable` for the meaning of each field.
+///
+/// They change the result of the transactions, so all the nodes of a network must use the same
+/// config, and it must not be changed on a running chain, like the genesis.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct StakingConfig {
+    pub max_used_validator_addr: usize,
+    pub voting_power_unit: u64,
+    pub immediate_unbond_penalty_divisor: u64,
+    pub jail_escalation_grace_period: Option<Timespec>,
+    pub idempotent_unjail_window: Option<Timespec>,
+    pub reject_svn_downgrade: bool,
+    pub community_node_minimal_staking: Option<Coin>,
+    pub fee_destination: FeeDestination,
+    pub min_unbond_amount: Coin,
+    pub permanent_jail: bool,
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self {
+            max_used_validator_addr: DEFAULT_MAX_USED_VALIDATOR_ADDR,
+            voting_power_unit: DEFAULT_VOTING_POWER_UNIT,
+            immediate_unbond_penalty_divisor: DEFAULT_IMMEDIATE_UNBOND_PENALTY_DIVISOR,
+            jail_escalation_grace_period: None,
+            idempotent_unjail_window: None,
+            reject_svn_downgrade: false,
+            community_node_minimal_staking: None,
+            fee_destination: FeeDestination::Burn,
+            min_unbond_amount: Coin::zero(),
+            permanent_jail: false,
+        }
+    }
+}
+
+impl StakingTable {
+    /// Apply the policies in `config`, the default config leaves the table as after `initialize`.
+    ///
+    /// # Panics
+    ///
+    /// Panic if `voting_power_unit` or `immediate_unbond_penalty_divisor` is zero.
+    pub fn configure(&mut self, config: &StakingConfig) {
+        assert!(
+            config.voting_power_unit > 0,
+            "voting power unit can't be zero"
+        );
+        assert!(
+            config.immediate_unbond_penalty_divisor > 0,
+            "penalty divisor can't be zero"
+        );
+        self.config = config.clone();
+    }
+}
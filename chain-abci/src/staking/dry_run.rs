@@ -0,0 +1,145 @@
+//! Validate staking transactions without modifying the state.
+//!
+//! The handlers are executed against a copy of the table and a write buffer over the heap,
+//! which are discarded afterwards, so the checks and the results are exactly the same as the
+//! real execution.
+use std::collections::HashMap;
+
+use chain_core::common::Timespec;
+use chain_core::init::coin::Coin;
+use chain_core::state::account::{
+    NodeMetadata, StakedState, StakedStateAddress, UnbondTx, UnjailTx,
+};
+use chain_core::state::validator::NodeJoinRequestTx;
+use chain_core::tx::fee::Fee;
+use chain_storage::buffer::{Get, GetStaking, SimpleStore};
+
+use super::table::StakingTable;
+use super::tx::{BlockContext, DepositResult, NodeJoinResult, UnbondResult, WithdrawResult};
+use crate::tx_error::{DepositError, NodeJoinError, PublicTxError, WithdrawError};
+
+/// Readonly heap with a discarded write buffer
+struct DryRunStore<'a, S> {
+    heap: &'a S,
+    buffer: HashMap<StakedStateAddress, StakedState>,
+}
+
+impl<'a, S: GetStaking> Get for DryRunStore<'a, S> {
+    type Key = StakedStateAddress;
+    type Value = StakedState;
+    fn get(&self, key: &Self::Key) -> Option<Self::Value> {
+        self.buffer.get(key).cloned().or_else(|| self.heap.get(key))
+    }
+}
+
+impl<'a, S: GetStaking> SimpleStore for DryRunStore<'a, S> {
+    fn set(&mut self, key: Self::Key, value: Self::Value) {
+        self.buffer.insert(key, value);
+    }
+}
+
+impl StakingTable {
+    /// Run `f` on a copy of the table and the buffered heap, discarding all the changes.
+    /// Complexity: O(N) to copy the table.
+    fn dry_run<'a, S: GetStaking, T>(
+        &self,
+        heap: &'a S,
+        f: impl FnOnce(&mut StakingTable, &mut DryRunStore<'a, S>) -> T,
+    ) -> T {
+        let mut table = self.clone();
+        let mut store = DryRunStore {
+            heap,
+            buffer: HashMap::new(),
+        };
+        f(&mut table, &mut store)
+    }
+
+    /// Validate `NodeJoinTx` like `node_join`, without modifying the heap or the table.
+    pub fn validate_node_join(
+        &self,
+        heap: &impl GetStaking,
+        ctx: &BlockContext,
+        recent_isv_svn: u16,
+        chain_hex_id: u8,
+        tx: &NodeJoinRequestTx,
+    ) -> Result<NodeJoinResult, PublicTxError> {
+        self.dry_run(heap, |table, store| {
+            table.node_join(store, ctx, recent_isv_svn, chain_hex_id, tx)
+        })
+    }
+
+    /// Check whether the account can join as a node now, returns the first blocker of
+    /// `node_join`: bonded coins, jailing, duplicated validator address, used validator address
+    /// slots and validator set capacity.
+    /// The nonce, the attributes and the keypackage are not checked, so it works without the
+    /// enclave.
+    pub fn can_join(
+        &self,
+        heap: &impl GetStaking,
+        ctx: &BlockContext,
+        tx: &NodeJoinRequestTx,
+    ) -> Result<(), NodeJoinError> {
+        self.dry_run(heap, |table, store| {
+            let mut staking = table.get_or_default(&*store, &tx.address);
+            if staking.bonded < table.node_join_minimal_staking(&tx.node_meta) {
+                return Err(NodeJoinError::BondedNotEnough);
+            }
+            match &tx.node_meta {
+                NodeMetadata::CouncilNode(cm) => table
+                    .council_node_join(&*store, &mut staking, ctx, cm)
+                    .map(|_| ()),
+                NodeMetadata::CommunityNode(_) if staking.node_meta.is_some() => {
+                    Err(NodeJoinError::AlreadyJoined)
+                }
+                NodeMetadata::CommunityNode(_) => Ok(()),
+            }
+        })
+    }
+
+    /// Validate `UnjailTx` like `unjail`, without modifying the heap or the table.
+    pub fn validate_unjail(
+        &self,
+        heap: &impl GetStaking,
+        ctx: &BlockContext,
+        tx: &UnjailTx,
+    ) -> Result<(), PublicTxError> {
+        self.dry_run(heap, |table, store| table.unjail(store, ctx, tx))
+    }
+
+    /// Validate deposit like `deposit`, without modifying the heap or the table.
+    pub fn validate_deposit(
+        &self,
+        heap: &impl GetStaking,
+        block_time: Timespec,
+        addr: &StakedStateAddress,
+        amount: Coin,
+    ) -> Result<DepositResult, DepositError> {
+        self.dry_run(heap, |table, store| {
+            table.deposit(store, block_time, addr, amount)
+        })
+    }
+
+    /// Validate `UnbondTx` like `unbond`, without modifying the heap or the table.
+    pub fn validate_unbond(
+        &self,
+        heap: &impl GetStaking,
+        ctx: &BlockContext,
+        tx: &UnbondTx,
+        fee: Fee,
+    ) -> Result<UnbondResult, PublicTxError> {
+        self.dry_run(heap, |table, store| table.unbond(store, ctx, tx, fee))
+    }
+
+    /// Validate withdraw like `withdraw`, without modifying the heap or the table.
+    pub fn validate_withdraw(
+        &self,
+        heap: &impl GetStaking,
+        ctx: &BlockContext,
+        addr: &StakedStateAddress,
+        amount: Coin,
+    ) -> Result<WithdrawResult, WithdrawError> {
+        self.dry_run(heap, |table, store| {
+            table.withdraw(store, ctx, addr, amount)
+        })
+    }
+}
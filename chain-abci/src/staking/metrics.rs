@@ -0,0 +1,68 @@
+//! Observability hooks of the staking operations, e.g. for prometheus counters,
+//! without coupling the staking logic to a metrics library.
+use std::fmt;
+use std::sync::Arc;
+
+use chain_core::common::Timespec;
+use chain_core::init::coin::Coin;
+use chain_core::state::account::StakedStateAddress;
+use chain_core::state::tendermint::TendermintValidatorAddress;
+
+/// Invoked at the end of the successful staking operations, all the hooks are no-op by default.
+pub trait StakingMetrics: Send + Sync {
+    /// Node joined or re-activated, `validator_address` is `None` for community node
+    fn on_join(
+        &self,
+        _addr: &StakedStateAddress,
+        _validator_address: Option<&TendermintValidatorAddress>,
+    ) {
+    }
+
+    /// `amount` moved from bonded to unbonded
+    fn on_unbond(&self, _addr: &StakedStateAddress, _amount: Coin) {}
+
+    /// `amount` removed from unbonded
+    fn on_withdraw(&self, _addr: &StakedStateAddress, _amount: Coin) {}
+
+    /// `amount` of bonded and unbonded coins slashed
+    fn on_slash(&self, _addr: &StakedStateAddress, _amount: Coin) {}
+
+    /// Validator jailed until `jailed_until`
+    fn on_jail(&self, _addr: &StakedStateAddress, _jailed_until: Timespec) {}
+}
+
+/// Optional metrics hook held by `StakingTable`.
+///
+/// It's not cloned nor encoded, the copies of the table (e.g. the mempool state and the dry runs)
+/// don't report, so the operations are only counted once on the consensus state.
+/// The table restored from storage or created in init chain doesn't have it either,
+/// `ChainNodeApp::with_staking_metrics` installs it on the consensus state in both cases.
+#[derive(Default)]
+pub(crate) struct MetricsHook(Option<Arc<dyn StakingMetrics>>);
+
+impl MetricsHook {
+    pub(crate) fn new(metrics: Arc<dyn StakingMetrics>) -> Self {
+        Self(Some(metrics))
+    }
+
+    /// Invoke `f` if the hook is set
+    pub(crate) fn report(&self, f: impl FnOnce(&dyn StakingMetrics)) {
+        if let Some(metrics) = &self.0 {
+            f(metrics.as_ref())
+        }
+    }
+}
+
+impl Clone for MetricsHook {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+impl fmt::Debug for MetricsHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MetricsHook")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
@@ -1,7 +1,20 @@
+mod config;
+mod dry_run;
+mod metrics;
 mod table;
 mod tx;
 
-pub use table::{RewardsDistribution, StakingTable};
+pub use config::StakingConfig;
+pub use metrics::StakingMetrics;
+pub use table::{
+    FeeDestination, InvariantViolation, RewardsDistribution, SnapshotError, StakingTable,
+    DEFAULT_IMMEDIATE_UNBOND_PENALTY_DIVISOR, DEFAULT_MAX_USED_VALIDATOR_ADDR,
+    DEFAULT_VOTING_POWER_UNIT, PRUNE_USED_ADDRESSES_INTERVAL, SNAPSHOT_VERSION,
+};
+pub use tx::{
+    BlockContext, DepositResult, NodeJoinResult, StakingChange, UnbondResult, WithdrawResult,
+    WithdrawToResult,
+};
 
 #[cfg(test)]
 mod tests {
@@ -11,26 +24,31 @@ mod tests {
     };
     use std::str::FromStr;
 
+    use chain_core::common::Timespec;
     use chain_core::init::address::RedeemAddress;
-    use chain_core::init::coin::Coin;
+    use chain_core::init::coin::{sum_coins, Coin};
     use chain_core::init::config::SlashRatio;
     use chain_core::init::params::NetworkParameters;
     use chain_core::state::account::{
-        NodeState, PunishmentKind, StakedState, StakedStateAddress, UnbondTx, UnjailTx, Validator,
+        JailReason, NodeCommonInfo, NodeMetadata, NodeState, PunishmentKind, StakedState,
+        StakedStateAddress, StakedStateOpAttributes, UnbondTx, UnjailTx, Validator,
     };
-    use chain_core::state::tendermint::{BlockHeight, TendermintValidatorPubKey};
-    use chain_core::state::validator::NodeJoinRequestTx;
+    use chain_core::state::tendermint::{
+        BlockHeight, TendermintValidatorAddress, TendermintValidatorPubKey, TendermintVotePower,
+    };
+    use chain_core::state::validator::{EditValidatorTx, NodeJoinRequestTx};
     use chain_core::tx::fee::Fee;
     use chain_storage::buffer::{Get, GetStaking, MemStore, StoreStaking};
     use test_common::chain_env::{
-        get_init_network_params, mock_council_node, mock_council_node_meta,
+        get_init_network_params, mock_confidential_init, mock_council_node, mock_council_node_meta,
     };
 
     use super::*;
     use crate::app::BeginBlockInfo;
-    use crate::staking::table::{PunishmentOutcome, SlashedCoin};
+    use crate::staking::table::{PunishmentOutcome, SlashedCoin, DEFAULT_MAX_VALIDATORS};
     use crate::tx_error::{
-        DepositError, NodeJoinError, PublicTxError, UnbondError, UnjailError, WithdrawError,
+        DepositError, NodeJoinError, PublicTxError, RebondError, UnbondError, UnjailError,
+        WithdrawError,
     };
 
     macro_rules! matches {
@@ -65,7 +83,16 @@ mod tests {
         staking
     }
 
+    /// Three genesis validators, the validator set size of `end_block` is 3, the node joins are not
+    /// capped, use `init_capped_staking_table` to test the cap.
     fn init_staking_table() -> (StakingTable, StakingMemStore) {
+        let (mut table, store) = init_capped_staking_table(3);
+        table.max_validators = DEFAULT_MAX_VALIDATORS;
+        (table, store)
+    }
+
+    /// Same as `init_staking_table`, the node joins are limited by `max_validators`
+    fn init_capped_staking_table(max_validators: usize) -> (StakingTable, StakingMemStore) {
         let minimal = Coin::new(10_0000_0000).unwrap();
         let genesis_accounts = vec![
             new_validator(
@@ -91,7 +118,7 @@ mod tests {
             StakingTable::from_genesis(
                 &store,
                 minimal,
-                3,
+                max_validators,
                 &genesis_accounts
                     .iter()
                     .map(|staking| staking.address)
@@ -109,7 +136,14 @@ mod tests {
         let nonce = store.get_or_default(&addr4).nonce;
 
         let amount = Coin::new(10_0000_0000).unwrap();
-        table.deposit(&mut store, &addr4, amount).unwrap();
+        assert_eq!(
+            table.deposit(&mut store, 0, &addr4, amount).unwrap(),
+            DepositResult {
+                addr: addr4,
+                amount,
+                unbonding_until: None,
+            }
+        );
 
         // deposit doesn't increase nonce
         assert_eq!(store.get(&addr4).unwrap().nonce, nonce);
@@ -120,14 +154,22 @@ mod tests {
             attributes: Default::default(),
             node_meta: mock_council_node(val_pk4.clone()),
         };
-        table.node_join(&mut store, 10, 0, 0, &node_join).unwrap();
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
         assert_eq!(table.end_block(&store, 3), vec![]);
         // node-join increase nonce by one
         assert_eq!(store.get(&addr4).unwrap().nonce, nonce + 1);
 
         // after deposit, replace one of the existing validator
         table
-            .deposit(&mut store, &addr4, Coin::new(2_0000_0000).unwrap())
+            .deposit(&mut store, 0, &addr4, Coin::new(2_0000_0000).unwrap())
             .unwrap();
         let val_pk1 = validator_pubkey(&[0xcc; 32]);
         assert_eq!(
@@ -149,9 +191,7 @@ mod tests {
         table
             .unbond(
                 &mut store,
-                10,
-                0,
-                BlockHeight::genesis(),
+                &BlockContext::new(0, BlockHeight::genesis(), 10),
                 &unbond,
                 Fee::zero(),
             )
@@ -168,223 +208,3678 @@ mod tests {
 
         // test withdraw transaction
         table
-            .withdraw(&mut store, 10 + 10, &addr4, Coin::new(2_0000_0000).unwrap())
+            .withdraw(
+                &mut store,
+                &BlockContext::new(10 + 10, 0.into(), 0),
+                &addr4,
+                Coin::new(2_0000_0000).unwrap(),
+            )
             .unwrap();
         // withdraw increase nonce by one
         assert_eq!(store.get(&addr4).unwrap().nonce, nonce + 2);
     }
 
     #[test]
-    fn check_jailing() {
-        let mut init_params = get_init_network_params(Coin::zero());
-        let slash_ratio: SlashRatio = "0.01".parse().unwrap();
-        init_params.slashing_config.liveness_slash_percent = slash_ratio;
-        init_params.slashing_config.byzantine_slash_percent = slash_ratio;
-        let params = NetworkParameters::Genesis(init_params);
-        let info = BeginBlockInfo {
-            params: &params,
-            max_evidence_age: 10,
-            block_time: 0,
-            block_height: 0.into(),
-            voters: &[],
-            evidences: &[],
+    fn check_node_join_result() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        let val_pk4 = validator_pubkey(&[0xcf; 32]);
+
+        // fresh insert
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk4.clone()),
+        };
+        let result = table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            NodeJoinResult {
+                isv_svn: 0,
+                validator_address: Some(TendermintValidatorAddress::from(&val_pk4)),
+                reactivated: false,
+                evicted: None,
+            }
+        );
+
+        // reactivate with a new key
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(11_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        table
+            .deposit(&mut store, 0, &addr1, Coin::new(11_0000_0000).unwrap())
+            .unwrap();
+        let val_pk_new = validator_pubkey(&[0x00; 32]);
+        let node_join = NodeJoinRequestTx {
+            nonce: 1,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk_new.clone()),
         };
+        let result = table
+            .node_join(
+                &mut store,
+                &BlockContext::new(1, 0.into(), 1),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            NodeJoinResult {
+                isv_svn: 0,
+                validator_address: Some(TendermintValidatorAddress::from(&val_pk_new)),
+                reactivated: true,
+                evicted: None,
+            }
+        );
+    }
 
+    #[test]
+    fn check_dry_run() {
         let (mut table, mut store) = init_staking_table();
         let addr1 = staking_address(&[0xcc; 32]);
-        let val_pk1 = validator_pubkey(&[0xcc; 32]);
-        let evidence = (val_pk1.clone().into(), 1.into(), 0);
-        let block_time: u64 = 0;
-        let punishment_outcomes = table.begin_block(
-            &mut store,
-            &BeginBlockInfo {
-                block_time,
-                block_height: 1.into(),
-                evidences: &[evidence.clone()],
-                ..info
-            },
+        let addr4 = staking_address(&[0xcf; 32]);
+        let val_pk4 = validator_pubkey(&[0xcf; 32]);
+        let amount = Coin::new(10_0000_0000).unwrap();
+
+        let event = table.validate_deposit(&store, 0, &addr4, amount).unwrap();
+        assert_eq!(store.get(&addr4), None);
+        assert_eq!(table.deposit(&mut store, 0, &addr4, amount).unwrap(), event);
+
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk4),
+        };
+        let result = table
+            .validate_node_join(
+                &store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        let staking = store.get(&addr4).unwrap();
+        assert_eq!(staking.nonce, 0);
+        assert_eq!(staking.node_meta, None);
+        assert_eq!(table.active_validators(&store).len(), 3);
+        assert_eq!(
+            table
+                .node_join(
+                    &mut store,
+                    &BlockContext::new(10, 0.into(), 0),
+                    0,
+                    0,
+                    &node_join
+                )
+                .unwrap(),
+            result
         );
 
-        let bonded_slashed = Coin::new(11_0000_0000).unwrap() * slash_ratio;
-        let unbonded_slashed = Coin::zero();
-        let punishment_outcome = PunishmentOutcome {
-            staking_address: addr1,
-            slashed_coin: SlashedCoin {
-                bonded: bonded_slashed,
-                unbonded: unbonded_slashed,
-            },
-            punishment_kind: PunishmentKind::ByzantineFault,
-            jailed_until: Some(block_time.saturating_add(info.get_unbonding_period())),
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(1_0000_0000).unwrap(),
+            attributes: Default::default(),
         };
-        assert_eq!(punishment_outcomes, vec![punishment_outcome]);
+        let event = table
+            .validate_unbond(
+                &store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
         let staking = store.get(&addr1).unwrap();
-        assert!(staking.is_jailed());
+        assert_eq!(staking.nonce, 0);
+        assert_eq!(staking.unbonded, Coin::zero());
         assert_eq!(
-            table.end_block(&store, 3),
-            vec![(val_pk1.clone(), Coin::zero().into())]
+            table
+                .unbond(
+                    &mut store,
+                    &BlockContext::new(0, 1.into(), 10),
+                    &unbond,
+                    Fee::zero()
+                )
+                .unwrap(),
+            event
         );
 
-        let nonce = store.get(&addr1).unwrap().nonce;
-        // slashing/jailing don't increase nonce
-        assert_eq!(nonce, 0);
+        // errors are the same
+        assert!(matches!(
+            table.validate_unbond(
+                &store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero()
+            ),
+            Err(PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 0
+            })
+        ));
+        assert!(matches!(
+            table.validate_withdraw(
+                &store,
+                &BlockContext::new(0, 0.into(), 0),
+                &addr1,
+                unbond.value
+            ),
+            Err(WithdrawError::InUnbondingPeriod)
+        ));
+        let event = table
+            .validate_withdraw(
+                &store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                unbond.value,
+            )
+            .unwrap();
+        assert_eq!(store.get(&addr1).unwrap().unbonded, unbond.value);
+        assert_eq!(
+            table
+                .withdraw(
+                    &mut store,
+                    &BlockContext::new(10, 0.into(), 0),
+                    &addr1,
+                    unbond.value
+                )
+                .unwrap(),
+            event
+        );
+    }
 
-        // byzantine faults won't slashed again.
-        let punishment_outcomes = table.begin_block(
-            &mut store,
-            &BeginBlockInfo {
-                block_time: 1,
-                block_height: 2.into(),
-                evidences: &[evidence],
-                ..info
-            },
+    #[test]
+    fn check_lookup_address() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        let val_pk4 = validator_pubkey(&[0xcf; 32]);
+        let val_addr4 = TendermintValidatorAddress::from(&val_pk4);
+        assert_eq!(table.lookup_address(&val_addr4), None);
+
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk4),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert_eq!(table.lookup_address(&val_addr4), Some(&addr4));
+        assert_eq!(
+            table.lookup_address(&TendermintValidatorAddress::from(&validator_pubkey(
+                &[0x00; 32]
+            ))),
+            None
         );
-        assert_eq!(punishment_outcomes, vec![]);
+    }
+
+    #[test]
+    fn check_node_join_svn_downgrade() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+
+        // the most recent isv_svn is kept by default
+        let result = table
+            .clone()
+            .node_join(
+                &mut store.clone(),
+                &BlockContext::new(10, 0.into(), 0),
+                1,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert_eq!(result.isv_svn, 1);
+
+        // the mock enclave isv_svn is 0
+        let mut table = table.with_reject_svn_downgrade(true);
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                1,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::StaleEnclaveVersion { got: 0, floor: 1 }
+            ))
+        ));
+        let result = table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert_eq!(result.isv_svn, 0);
+    }
+
+    #[test]
+    fn check_node_join_validator_set_full() {
+        let (mut table, mut store) = init_capped_staking_table(3);
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        let val_pk4 = validator_pubkey(&[0xcf; 32]);
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk4.clone()),
+        };
+
+        // equal to the lowest-staked validator, rejected
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(11_0000_0000).unwrap())
+            .unwrap();
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::ValidatorSetFull))
+        ));
+        assert_eq!(store.get(&addr4).unwrap().nonce, 0);
+
+        // outbid the lowest-staked validator
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(1).unwrap())
+            .unwrap();
+        let result = table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert_eq!(result.evicted, Some(addr1));
+        assert_eq!(
+            table.end_block(&store, 3),
+            vec![
+                (val_pk4, Coin::new(11_0000_0001).unwrap().into()),
+                (validator_pubkey(&[0xcc; 32]), Coin::zero().into())
+            ]
+        );
+    }
+
+    #[test]
+    fn check_max_validators_from_network_params() {
+        use parity_scale_codec::{Decode, Encode};
+
+        let mut init_params = get_init_network_params(Coin::zero());
+        init_params.max_validators = 3;
+        let params = NetworkParameters::Genesis(init_params);
+        let (mut table, mut store) = init_capped_staking_table(params.get_max_validators());
+        // the node joins are limited by the same max validators as end block
+        assert_eq!(table.max_validators, params.get_max_validators());
+        assert_eq!(table.get_chosen_validators().len(), table.max_validators);
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::ValidatorSetFull))
+        ));
+        assert!(table
+            .end_block(&store, params.get_max_validators())
+            .is_empty());
+
+        // restored from storage
+        let mut restored = StakingTable::decode(&mut table.encode().as_slice()).unwrap();
+        restored.initialize(
+            &store,
+            table.minimal_required_staking,
+            params.get_max_validators(),
+        );
+        assert_eq!(restored.max_validators, params.get_max_validators());
+        assert_eq!(
+            restored.min_bond_to_join(&store),
+            table.min_bond_to_join(&store)
+        );
+    }
+
+    #[test]
+    fn check_min_bond_to_join() {
+        let (table, store) = init_staking_table();
+        // not full
+        assert_eq!(
+            table.min_bond_to_join(&store),
+            Coin::new(10_0000_0000).unwrap()
+        );
+        let (table, store) = init_capped_staking_table(4);
+        assert_eq!(
+            table.min_bond_to_join(&store),
+            Coin::new(10_0000_0000).unwrap()
+        );
+
+        // full, outbid the lowest-staked validator
+        let (table, store) = init_capped_staking_table(3);
+        assert_eq!(
+            table.min_bond_to_join(&store),
+            Coin::new(11_0000_0001).unwrap()
+        );
+        let (table, store) = init_capped_staking_table(1);
+        assert_eq!(
+            table.min_bond_to_join(&store),
+            Coin::new(13_0000_0001).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_eviction_tie_break() {
+        let minimal = Coin::new(10_0000_0000).unwrap();
+        let seeds = [[0xce; 32], [0xcc; 32], [0xcd; 32]];
+        let mut store = StakingMemStore::new();
+        for seed in seeds.iter() {
+            store.set_staking(new_validator(seed, minimal));
+        }
+        let mut addresses = seeds.iter().map(staking_address).collect::<Vec<_>>();
+        let greatest = *addresses.iter().max().unwrap();
+
+        // the greatest address among the tied lowest-staked, regardless of the insertion order
+        for _ in 0..addresses.len() {
+            addresses.rotate_left(1);
+            let table = StakingTable::from_genesis(&store, minimal, 3, &addresses);
+            assert_eq!(table.select_eviction_candidate(&store).unwrap(), greatest);
+            let table = StakingTable::from_genesis(&store, minimal, 4, &addresses);
+            assert!(table.select_eviction_candidate(&store).is_none());
+        }
+    }
+
+    fn mock_community_node() -> NodeMetadata {
+        NodeMetadata::CommunityNode(NodeCommonInfo {
+            name: "no-name".to_string(),
+            security_contact: None,
+            confidential_init: mock_confidential_init(),
+        })
+    }
+
+    #[test]
+    fn check_community_node_minimal_staking() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_community_node_minimal_staking(Coin::new(1_0000_0000).unwrap());
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(1_0000_0000).unwrap())
+            .unwrap();
+
+        // council node still requires the minimal required staking
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::BondedNotEnough))
+        ));
+
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_community_node(),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert!(matches!(
+            store.get(&addr4).unwrap().node_meta,
+            Some(NodeState::CommunityNode(_))
+        ));
+    }
+
+    #[test]
+    fn check_community_node_join() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+
+        // not enough bonded
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(9_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_community_node(),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::BondedNotEnough))
+        ));
+
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(1_0000_0000).unwrap())
+            .unwrap();
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        let staking = store.get(&addr4).unwrap();
+        assert_eq!(staking.nonce, 1);
+        assert!(matches!(
+            staking.node_meta,
+            Some(NodeState::CommunityNode(_))
+        ));
+        // community node is not a validator
+        assert_eq!(table.end_block(&store, 3), vec![]);
+        assert!(table.get_chosen_validators().get(&addr4).is_none());
+
+        // can't join twice
+        let node_join = NodeJoinRequestTx {
+            nonce: 1,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_community_node(),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::AlreadyJoined))
+        ));
+
+        // can upgrade to council node
+        let val_pk4 = validator_pubkey(&[0xcf; 32]);
+        let node_join = NodeJoinRequestTx {
+            nonce: 1,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk4.clone()),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        assert!(store.get(&addr4).unwrap().has_council_node_meta());
+        assert_eq!(
+            table.end_block(&store, 4),
+            vec![(val_pk4, Coin::new(10_0000_0000).unwrap().into())]
+        );
+    }
+
+    #[test]
+    fn check_node_join_chain_hex_id() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: StakedStateOpAttributes::new(0xab),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0xac,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::WrongChainHexId))
+        ));
+        // failed execution don't increase nonce
+        assert_eq!(store.get(&addr4).unwrap().nonce, 0);
+
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0xab,
+                &node_join,
+            )
+            .unwrap();
+        assert!(store.get(&addr4).unwrap().has_council_node_meta());
+    }
+
+    #[test]
+    fn check_unbond_immediate() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_immediate_unbond_penalty_divisor(5);
+        let addr1 = staking_address(&[0xcc; 32]);
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(5_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        let fee = Fee::new(Coin::new(1000).unwrap());
+        let (event, penalty) = table
+            .unbond_immediate(
+                &mut store,
+                &BlockContext::new(10, 1.into(), 0),
+                &unbond,
+                fee,
+            )
+            .unwrap();
+        assert_eq!(penalty, Coin::new(1_0000_0000).unwrap());
+        assert_eq!(
+            event,
+            UnbondResult {
+                addr: addr1,
+                amount: Coin::new(5_0000_0000).unwrap(),
+                unbonded_from: 10,
+                fee: fee.to_coin(),
+                deactivated: false,
+            }
+        );
+        let staking = store.get(&addr1).unwrap();
+        // 11 - 5 - 1 - fee
+        assert_eq!(staking.bonded, Coin::new(4_9999_9000).unwrap());
+        assert_eq!(staking.unbonded, Coin::new(5_0000_0000).unwrap());
+        assert_eq!(staking.unbonded_from, 10);
+        assert_eq!(staking.nonce, 1);
+
+        // can withdraw immediately
+        table
+            .withdraw(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                staking.unbonded,
+            )
+            .unwrap();
+        assert_eq!(store.get(&addr1).unwrap().unbonded, Coin::zero());
+
+        // bonded not enough to cover the penalty
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 2,
+            value: Coin::new(4_9999_9000).unwrap(),
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unbond_immediate(
+                &mut store,
+                &BlockContext::new(10, 1.into(), 0),
+                &unbond,
+                Fee::zero()
+            ),
+            Err(PublicTxError::Unbond(
+                UnbondError::InsufficientBonded { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn check_min_unbond_amount() {
+        let (table, mut store) = init_staking_table();
+        let min = Coin::new(1_0000_0000).unwrap();
+        let mut table = table.with_min_unbond_amount(min);
+        let addr1 = staking_address(&[0xcc; 32]);
+        let mut unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: (min - Coin::unit()).unwrap(),
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero()
+            ),
+            Err(PublicTxError::Unbond(UnbondError::BelowMinimum { min: m })) if m == min
+        ));
+        assert_eq!(store.get(&addr1).unwrap().nonce, 0);
+
+        // exactly the minimal amount
+        unbond.value = min;
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert_eq!(store.get(&addr1).unwrap().unbonded, min);
+    }
+
+    #[test]
+    fn check_staking_config() {
+        let (mut table, mut store) = init_staking_table();
+        let config: StakingConfig =
+            serde_yaml::from_str("min_unbond_amount: \"100000000\"\nmax_used_validator_addr: 2\n")
+                .unwrap();
+        // the unspecified ones are default
+        assert_eq!(
+            config,
+            StakingConfig {
+                min_unbond_amount: Coin::new(1_0000_0000).unwrap(),
+                max_used_validator_addr: 2,
+                ..Default::default()
+            }
+        );
+
+        table.configure(&config);
+        assert_eq!(table.config.max_used_validator_addr, 2);
+        assert_eq!(table.config.voting_power_unit, DEFAULT_VOTING_POWER_UNIT);
+        let unbond = UnbondTx {
+            from_staked_account: staking_address(&[0xcc; 32]),
+            nonce: 0,
+            value: Coin::unit(),
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero()
+            ),
+            Err(PublicTxError::Unbond(UnbondError::BelowMinimum { .. }))
+        ));
+
+        // the default config restores the defaults
+        table.configure(&StakingConfig::default());
+        assert_eq!(
+            table.config.max_used_validator_addr,
+            DEFAULT_MAX_USED_VALIDATOR_ADDR
+        );
+        assert_eq!(table.config.min_unbond_amount, Coin::zero());
+    }
+
+    #[test]
+    fn check_locked_deposit() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        let amount = Coin::new(1_0000_0000).unwrap();
+        table.deposit(&mut store, 0, &addr4, amount).unwrap();
+        // a locked deposit from the others only locks the deposited amount
+        table
+            .deposit_locked(&mut store, 0, &addr4, amount, Some(100))
+            .unwrap();
+        assert_eq!(store.get(&addr4).unwrap().bond_locks, vec![(amount, 100)]);
+
+        let unbond = |nonce| UnbondTx {
+            from_staked_account: addr4,
+            nonce,
+            value: amount,
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(99, 1.into(), 10),
+                &unbond(0),
+                Fee::zero(),
+            )
+            .unwrap();
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &BlockContext::new(99, 1.into(), 10),
+                &unbond(1),
+                Fee::zero()
+            ),
+            Err(PublicTxError::Unbond(UnbondError::BondLocked {
+                until: 100
+            }))
+        ));
+        assert_eq!(store.get(&addr4).unwrap().nonce, 1);
+
+        // unlocked after expiry
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(100, 1.into(), 10),
+                &unbond(1),
+                Fee::zero(),
+            )
+            .unwrap();
+        let staking = store.get(&addr4).unwrap();
+        assert_eq!(staking.bonded, Coin::zero());
+        assert_eq!(
+            staking.total_unbonded().unwrap(),
+            (amount + amount).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_unbond_coin_errors() {
+        let (mut table, mut store) = init_staking_table();
+        let addr = staking_address(&[0xcf; 32]);
+        let ctx = BlockContext::new(0, 1.into(), 10);
+        let unbond = |value| UnbondTx {
+            from_staked_account: addr,
+            nonce: 0,
+            value,
+            attributes: Default::default(),
+        };
+        let mut staking = StakedState::default(addr);
+        staking.bonded = Coin::one();
+
+        // unbonded + value reaches or exceeds the max coin
+        staking.unbonded = (Coin::max() - Coin::one()).unwrap();
+        store.set_staking(staking.clone());
+        table
+            .unbond(&mut store, &ctx, &unbond(Coin::one()), Fee::zero())
+            .unwrap();
+        store.set_staking(staking.clone());
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &ctx,
+                &unbond((Coin::one() + Coin::unit()).unwrap()),
+                Fee::zero()
+            ),
+            Err(PublicTxError::Unbond(UnbondError::UnbondedOverflow))
+        ));
+
+        // value + fee exceeds the max coin
+        staking.unbonded = Coin::zero();
+        store.set_staking(staking.clone());
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &ctx,
+                &unbond(Coin::max()),
+                Fee::new(Coin::unit())
+            ),
+            Err(PublicTxError::Unbond(UnbondError::FeeOverflow))
+        ));
+
+        // bonded - (value + fee) is rejected before subtracting, so `BondedUnderflow` is not
+        // reachable from a valid tx
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &ctx,
+                &unbond(Coin::one()),
+                Fee::new(Coin::unit())
+            ),
+            Err(PublicTxError::Unbond(
+                UnbondError::InsufficientBonded { .. }
+            ))
+        ));
+        assert_eq!(store.get(&addr).unwrap(), staking);
+    }
+
+    #[test]
+    fn check_unbond_fee_destination() {
+        let addr1 = staking_address(&[0xcc; 32]);
+        let treasury = staking_address(&[0xcf; 32]);
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(1_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        let fee = Fee::new(Coin::new(1000).unwrap());
+        let total = |table: &StakingTable, store: &StakingMemStore| {
+            let (bonded, unbonded) = table.total_staked(store).unwrap();
+            (bonded + unbonded).unwrap()
+        };
+
+        // burned by default
+        let (mut table, mut store) = init_staking_table();
+        let total_before = total(&table, &store);
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                fee,
+            )
+            .unwrap();
+        assert_eq!(
+            total(&table, &store),
+            (total_before - fee.to_coin()).unwrap()
+        );
+
+        // credited to the treasury, the supply is conserved
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_fee_destination(FeeDestination::Treasury(treasury));
+        let total_before = total(&table, &store);
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                fee,
+            )
+            .unwrap();
+        assert_eq!(total(&table, &store), total_before);
+        assert_eq!(store.get(&treasury).unwrap().bonded, fee.to_coin());
+
+        // the treasury can be the unbonding account itself
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_fee_destination(FeeDestination::Treasury(addr1));
+        let total_before = total(&table, &store);
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                fee,
+            )
+            .unwrap();
+        assert_eq!(total(&table, &store), total_before);
+        assert_eq!(store.get(&addr1).unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn check_unbond_deactivate() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let mut unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(1_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        let deactivated = |result: UnbondResult| result.deactivated;
+
+        // the remaining bonded is still enough
+        let event = table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert!(!deactivated(event));
+
+        // below the minimal required staking
+        unbond.nonce = 1;
+        let event = table
+            .unbond(
+                &mut store,
+                &BlockContext::new(5, 2.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert!(deactivated(event));
+        match store.get(&addr1).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => {
+                assert_eq!(val.inactive_time, Some(5));
+                assert_eq!(val.inactive_block, Some(2.into()));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            table.end_block(&store, 3),
+            vec![(validator_pubkey(&[0xcc; 32]), Coin::zero().into())]
+        );
+
+        // already inactive
+        unbond.nonce = 2;
+        let event = table
+            .unbond(
+                &mut store,
+                &BlockContext::new(6, 3.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert!(!deactivated(event));
+    }
+
+    #[test]
+    fn check_withdraw_partial() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(5_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        let event = table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        let unbonded_from = 10;
+        assert_eq!(
+            event,
+            UnbondResult {
+                addr: addr1,
+                amount: Coin::new(5_0000_0000).unwrap(),
+                unbonded_from,
+                fee: Coin::zero(),
+                deactivated: false,
+            }
+        );
+
+        assert!(matches!(
+            table.withdraw_partial(
+                &mut store,
+                &BlockContext::new(5, 0.into(), 0),
+                &addr1,
+                Coin::unit()
+            ),
+            Err(WithdrawError::InUnbondingPeriod)
+        ));
+        assert!(matches!(
+            table.withdraw_partial(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                Coin::zero()
+            ),
+            Err(WithdrawError::ZeroValue)
+        ));
+        assert!(matches!(
+            table.withdraw_partial(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                Coin::new(6_0000_0000).unwrap()
+            ),
+            Err(WithdrawError::UnbondedNotEnough(_, _))
+        ));
+
+        let event = table
+            .withdraw_partial(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                Coin::new(2_0000_0000).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            event,
+            WithdrawResult {
+                addr: addr1,
+                amount: Coin::new(2_0000_0000).unwrap(),
+            }
+        );
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.unbonded, Coin::new(3_0000_0000).unwrap());
+        assert_eq!(staking.unbonded_from, unbonded_from);
+        assert_eq!(staking.nonce, 2);
+
+        // withdraw the remaining amount
+        table
+            .withdraw_partial(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                Coin::new(3_0000_0000).unwrap(),
+            )
+            .unwrap();
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.unbonded, Coin::zero());
+        assert_eq!(staking.nonce, 3);
+    }
+
+    #[test]
+    fn check_withdraw_all() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        assert!(matches!(
+            table.withdraw_all(&mut store, &BlockContext::new(10, 0.into(), 0), &addr1),
+            Err(WithdrawError::ZeroValue)
+        ));
+        let mut unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(2_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        let cached = store.get(&addr1).unwrap().unbonded;
+
+        // unbonded grows after the client read it
+        unbond.nonce = 1;
+        unbond.value = Coin::new(3_0000_0000).unwrap();
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert!(matches!(
+            table.withdraw(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                cached
+            ),
+            Err(WithdrawError::UnbondedSanityCheck(_, _))
+        ));
+
+        assert!(matches!(
+            table.withdraw_all(&mut store, &BlockContext::new(9, 0.into(), 0), &addr1),
+            Err(WithdrawError::InUnbondingPeriod)
+        ));
+        assert_eq!(
+            table
+                .withdraw_all(&mut store, &BlockContext::new(10, 0.into(), 0), &addr1)
+                .unwrap(),
+            WithdrawResult {
+                addr: addr1,
+                amount: Coin::new(5_0000_0000).unwrap(),
+            }
+        );
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.unbonded, Coin::zero());
+        assert_eq!(staking.nonce, 3);
+    }
+
+    #[test]
+    fn check_withdraw_all_matured_batches() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        // matured at 10, 15 and 30
+        for (nonce, (time, value)) in [(0, 2_0000_0000), (5, 3_0000_0000), (20, 1_0000_0000)]
+            .iter()
+            .enumerate()
+        {
+            let unbond = UnbondTx {
+                from_staked_account: addr1,
+                nonce: nonce as u64,
+                value: Coin::new(*value).unwrap(),
+                attributes: Default::default(),
+            };
+            table
+                .unbond(
+                    &mut store,
+                    &BlockContext::new(*time, 1.into(), 10),
+                    &unbond,
+                    Fee::zero(),
+                )
+                .unwrap();
+        }
+        assert_eq!(store.get(&addr1).unwrap().unbonding_queue.len(), 2);
+
+        // both matured batches are withdrawn in one step
+        assert_eq!(
+            table
+                .withdraw_all(&mut store, &BlockContext::new(20, 0.into(), 0), &addr1)
+                .unwrap(),
+            WithdrawResult {
+                addr: addr1,
+                amount: Coin::new(5_0000_0000).unwrap(),
+            }
+        );
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.unbonded, Coin::new(1_0000_0000).unwrap());
+        assert_eq!(staking.unbonded_from, 30);
+        assert!(staking.unbonding_queue.is_empty());
+        assert_eq!(staking.nonce, 4);
+        assert!(matches!(
+            table.withdraw_all(&mut store, &BlockContext::new(25, 0.into(), 0), &addr1),
+            Err(WithdrawError::InUnbondingPeriod)
+        ));
+    }
+
+    #[test]
+    fn check_withdraw_to() {
+        use chain_core::tx::data::{address::ExtendedAddr, output::TxOut};
+
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let amount = Coin::new(2_0000_0000).unwrap();
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: amount,
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+
+        let ctx = BlockContext::new(10, 0.into(), 0);
+        let output = TxOut::new(ExtendedAddr::OrTree([0xaa; 32]), amount);
+        let wrong_output = TxOut::new(ExtendedAddr::OrTree([0xaa; 32]), Coin::one());
+        assert!(matches!(
+            table.withdraw_to(&mut store, &ctx, &addr1, amount, wrong_output),
+            Err(WithdrawError::OutputValueMismatch(_, _))
+        ));
+        let event = table
+            .withdraw_to(&mut store, &ctx, &addr1, amount, output.clone())
+            .unwrap();
+        assert_eq!(
+            event,
+            WithdrawToResult {
+                addr: addr1,
+                output
+            }
+        );
+        assert_eq!(store.get(&addr1).unwrap().unbonded, Coin::zero());
+    }
+
+    #[test]
+    fn check_sequential_txs_in_block() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let ctx = BlockContext::new(0, 1.into(), 10);
+        let unbond = |nonce| UnbondTx {
+            from_staked_account: addr1,
+            nonce,
+            value: Coin::new(1_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+
+        // two txs from the same account in one block, executed against the same buffer
+        table
+            .unbond(&mut store, &ctx, &unbond(0), Fee::zero())
+            .unwrap();
+        table
+            .unbond(&mut store, &ctx, &unbond(1), Fee::zero())
+            .unwrap();
+        // replayed or out of order nonces are rejected
+        for nonce in [0, 1, 3].iter() {
+            assert!(matches!(
+                table.unbond(&mut store, &ctx, &unbond(*nonce), Fee::zero()),
+                Err(PublicTxError::IncorrectNonce { expected: 2, .. })
+            ));
+        }
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.nonce, 2);
+        assert_eq!(staking.unbonded, Coin::new(2_0000_0000).unwrap());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "stale write")]
+    fn check_stale_staking_write() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let stale = store.get(&addr1).unwrap();
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(1_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        crate::staking::table::set_staking(&mut store, stale, table.minimal_required_staking);
+    }
+
+    #[test]
+    fn check_rebond() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(5_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            table.rebond(&mut store, &addr1, Coin::unit(), 0),
+            Err(PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 0
+            })
+        ));
+        assert!(matches!(
+            table.rebond(&mut store, &addr1, Coin::zero(), 1),
+            Err(PublicTxError::Rebond(RebondError::ZeroValue))
+        ));
+        assert!(matches!(
+            table.rebond(&mut store, &addr1, Coin::new(6_0000_0000).unwrap(), 1),
+            Err(PublicTxError::Rebond(RebondError::UnbondedNotEnough(_, _)))
+        ));
+
+        // rebond before unbonded_from
+        table
+            .rebond(&mut store, &addr1, Coin::new(2_0000_0000).unwrap(), 1)
+            .unwrap();
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.bonded, Coin::new(8_0000_0000).unwrap());
+        assert_eq!(staking.unbonded, Coin::new(3_0000_0000).unwrap());
+        assert_eq!(
+            (staking.bonded + staking.unbonded).unwrap(),
+            Coin::new(11_0000_0000).unwrap()
+        );
+        assert_eq!(staking.nonce, 2);
+    }
+
+    #[test]
+    fn check_unbonding_queue() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let mut unbond = |store: &mut StakingMemStore, block_time, nonce, value| {
+            let tx = UnbondTx {
+                from_staked_account: addr1,
+                nonce,
+                value: Coin::new(value).unwrap(),
+                attributes: Default::default(),
+            };
+            table
+                .unbond(
+                    store,
+                    &BlockContext::new(block_time, 1.into(), 10),
+                    &tx,
+                    Fee::zero(),
+                )
+                .unwrap();
+        };
+        unbond(&mut store, 0, 0, 2_0000_0000);
+        unbond(&mut store, 5, 1, 3_0000_0000);
+        unbond(&mut store, 7, 2, 1_0000_0000);
+
+        // the second unbond doesn't delay the first one
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.unbonded, Coin::new(2_0000_0000).unwrap());
+        assert_eq!(staking.unbonded_from, 10);
+        assert_eq!(
+            staking.unbonding_queue,
+            vec![
+                (Coin::new(3_0000_0000).unwrap(), 15),
+                (Coin::new(1_0000_0000).unwrap(), 17)
+            ]
+        );
+        assert_eq!(
+            staking.total_unbonded().unwrap(),
+            Coin::new(6_0000_0000).unwrap()
+        );
+
+        // the latest unbonded is rebonded first
+        table
+            .rebond(&mut store, &addr1, Coin::new(2_0000_0000).unwrap(), 3)
+            .unwrap();
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(
+            staking.unbonding_queue,
+            vec![(Coin::new(2_0000_0000).unwrap(), 15)]
+        );
+
+        // only the matured portion is released
+        table
+            .withdraw(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                Coin::new(2_0000_0000).unwrap(),
+            )
+            .unwrap();
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.unbonded, Coin::new(2_0000_0000).unwrap());
+        assert_eq!(staking.unbonded_from, 15);
+        assert!(staking.unbonding_queue.is_empty());
+        assert!(matches!(
+            table.withdraw(
+                &mut store,
+                &BlockContext::new(14, 0.into(), 0),
+                &addr1,
+                staking.unbonded
+            ),
+            Err(WithdrawError::InUnbondingPeriod)
+        ));
+        table
+            .withdraw(
+                &mut store,
+                &BlockContext::new(15, 0.into(), 0),
+                &addr1,
+                staking.unbonded,
+            )
+            .unwrap();
+        assert_eq!(
+            store.get(&addr1).unwrap().total_unbonded().unwrap(),
+            Coin::zero()
+        );
+    }
+
+    #[test]
+    fn check_unbonding_schedule() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        assert!(table.unbonding_schedule(&store, &addr1).is_empty());
+        assert!(table
+            .unbonding_schedule(&store, &staking_address(&[0xff; 32]))
+            .is_empty());
+
+        let tx = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(2_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &tx,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert_eq!(
+            table.unbonding_schedule(&store, &addr1),
+            vec![(Coin::new(2_0000_0000).unwrap(), 10)]
+        );
+    }
+
+    #[test]
+    fn check_jailing() {
+        let mut init_params = get_init_network_params(Coin::zero());
+        let slash_ratio: SlashRatio = "0.01".parse().unwrap();
+        init_params.slashing_config.liveness_slash_percent = slash_ratio;
+        init_params.slashing_config.byzantine_slash_percent = slash_ratio;
+        let params = NetworkParameters::Genesis(init_params);
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 0,
+            block_height: 0.into(),
+            voters: &[],
+            evidences: &[],
+        };
+
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let val_pk1 = validator_pubkey(&[0xcc; 32]);
+        let evidence = (val_pk1.clone().into(), 1.into(), 0);
+        let block_time: u64 = 0;
+        let punishment_outcomes = table.begin_block(
+            &mut store,
+            &BeginBlockInfo {
+                block_time,
+                block_height: 1.into(),
+                evidences: &[evidence.clone()],
+                ..info
+            },
+        );
+
+        let bonded_slashed = Coin::new(11_0000_0000).unwrap() * slash_ratio;
+        let unbonded_slashed = Coin::zero();
+        let punishment_outcome = PunishmentOutcome {
+            staking_address: addr1,
+            slashed_coin: SlashedCoin {
+                bonded: bonded_slashed,
+                unbonded: unbonded_slashed,
+            },
+            punishment_kind: PunishmentKind::ByzantineFault,
+            jailed_until: Some(block_time.saturating_add(info.get_unbonding_period())),
+        };
+        assert_eq!(punishment_outcomes, vec![punishment_outcome]);
+        let staking = store.get(&addr1).unwrap();
+        assert!(staking.is_jailed());
+        assert_eq!(
+            table.end_block(&store, 3),
+            vec![(val_pk1.clone(), Coin::zero().into())]
+        );
+
+        let nonce = store.get(&addr1).unwrap().nonce;
+        // slashing/jailing don't increase nonce
+        assert_eq!(nonce, 0);
+
+        // byzantine faults won't slashed again.
+        let punishment_outcomes = table.begin_block(
+            &mut store,
+            &BeginBlockInfo {
+                block_time: 1,
+                block_height: 2.into(),
+                evidences: &[evidence],
+                ..info
+            },
+        );
+        assert_eq!(punishment_outcomes, vec![]);
+
+        // transaction denied after jailed
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce,
+            value: staking.bonded,
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unbond(
+                &mut store,
+                &BlockContext::new(2, 3.into(), 10),
+                &unbond,
+                Fee::zero()
+            ),
+            Err(PublicTxError::Unbond(UnbondError::IsJailed))
+        ));
+        assert!(matches!(
+            table.deposit(&mut store, 0, &addr1, Coin::new(2_0000_0000).unwrap()),
+            Err(DepositError::IsJailed)
+        ));
+        assert!(matches!(
+            table.withdraw(
+                &mut store,
+                &BlockContext::new(0, 0.into(), 0),
+                &addr1,
+                staking.unbonded
+            ),
+            Err(WithdrawError::IsJailed)
+        ));
+        let val_pk_new = validator_pubkey(&[0xcf; 32]);
+        let node_join = NodeJoinRequestTx {
+            nonce,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk_new),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(3, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::IsJailed))
+        ));
+        // failed execution don't increase nonce
+        assert_eq!(store.get(&addr1).unwrap().nonce, nonce);
+
+        // unjail
+        let tx = UnjailTx {
+            nonce,
+            address: addr1,
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unjail(&mut store, &BlockContext::new(1 + 1, 0.into(), 0), &tx),
+            Err(PublicTxError::Unjail(UnjailError::JailTimeNotExpired))
+        ));
+        table
+            .unjail(&mut store, &BlockContext::new(1 + 10, 0.into(), 0), &tx)
+            .unwrap();
+        // unjail increase nonce by one
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.nonce, nonce + 1);
+        assert!(!staking.is_jailed());
+    }
+
+    #[test]
+    fn check_jail_reason() {
+        let (mut table, mut store) = init_staking_table();
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let slash_ratio: SlashRatio = "0.1".parse().unwrap();
+        let jailed_until = 1 + info.get_unbonding_period();
+        let jail_reason = |store: &StakingMemStore, addr: &StakedStateAddress| match store
+            .get(addr)
+            .unwrap()
+            .node_meta
+        {
+            Some(NodeState::CouncilNode(val)) => val.jail_reason,
+            _ => None,
+        };
+
+        // non-live fault is recoverable
+        let addr1 = staking_address(&[0xcc; 32]);
+        table.slash(
+            &mut store,
+            &info,
+            &addr1,
+            slash_ratio,
+            PunishmentKind::NonLive,
+        );
+        assert_eq!(jail_reason(&store, &addr1), Some(JailReason::NonLive));
+        let tx = UnjailTx {
+            nonce: 0,
+            address: addr1,
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(jailed_until - 1, 0.into(), 0),
+                &tx
+            ),
+            Err(PublicTxError::Unjail(UnjailError::JailTimeNotExpired))
+        ));
+        table
+            .unjail(
+                &mut store,
+                &BlockContext::new(jailed_until, 0.into(), 0),
+                &tx,
+            )
+            .unwrap();
+        // unjail increase nonce by one
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.nonce, 1);
+        assert!(!staking.is_jailed());
+        assert_eq!(jail_reason(&store, &addr1), None);
+
+        // byzantine fault is recoverable by default
+        let addr2 = staking_address(&[0xcd; 32]);
+        table.slash(
+            &mut store,
+            &info,
+            &addr2,
+            slash_ratio,
+            PunishmentKind::ByzantineFault,
+        );
+        assert_eq!(
+            jail_reason(&store, &addr2),
+            Some(JailReason::ByzantineFault)
+        );
+        let tx = UnjailTx {
+            nonce: 0,
+            address: addr2,
+            attributes: Default::default(),
+        };
+        table
+            .unjail(
+                &mut store,
+                &BlockContext::new(jailed_until, 0.into(), 0),
+                &tx,
+            )
+            .unwrap();
+        assert!(!store.get(&addr2).unwrap().is_jailed());
+
+        // byzantine fault is permanent if enabled
+        let mut table = table.with_permanent_jail(true);
+        let addr3 = staking_address(&[0xce; 32]);
+        table.slash(
+            &mut store,
+            &info,
+            &addr3,
+            slash_ratio,
+            PunishmentKind::ByzantineFault,
+        );
+        assert_eq!(
+            jail_reason(&store, &addr3),
+            Some(JailReason::ByzantineFault)
+        );
+        assert!(store.get(&addr3).unwrap().is_permanently_jailed());
+        let tx = UnjailTx {
+            nonce: 0,
+            address: addr3,
+            attributes: Default::default(),
+        };
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(jailed_until, 0.into(), 0),
+                &tx
+            ),
+            Err(PublicTxError::Unjail(UnjailError::PermanentlyJailed))
+        ));
+        assert!(store.get(&addr3).unwrap().is_jailed());
+    }
+
+    #[test]
+    fn check_permanent_jail() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_permanent_jail(true);
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let addr1 = staking_address(&[0xcc; 32]);
+        let val_pk1 = validator_pubkey(&[0xcc; 32]);
+        let val_addr1 = TendermintValidatorAddress::from(&val_pk1);
+        let evidence = (val_addr1.clone(), 1.into(), 1);
+        let outcomes = table.begin_block(
+            &mut store,
+            &BeginBlockInfo {
+                evidences: &[evidence],
+                ..info
+            },
+        );
+        assert_eq!(outcomes[0].jailed_until, Some(Timespec::MAX));
+        let staking = store.get(&addr1).unwrap();
+        assert!(staking.is_permanently_jailed());
+
+        // the coins left after the slash can be unbonded and withdrawn
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: staking.bonded,
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(2, 2.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        let unbonded = store.get(&addr1).unwrap().unbonded;
+        table
+            .withdraw(
+                &mut store,
+                &BlockContext::new(2 + 10, 3.into(), 10),
+                &addr1,
+                unbonded,
+            )
+            .unwrap();
+        assert!(matches!(
+            table.deposit(&mut store, 0, &addr1, Coin::unit()),
+            Err(DepositError::IsJailed)
+        ));
+
+        // cleanup frees the indexes, but keeps the validator record
+        table.begin_block(
+            &mut store,
+            &BeginBlockInfo {
+                block_time: 1 + 10 + 1,
+                block_height: 4.into(),
+                ..info
+            },
+        );
+        assert!(table.lookup_address(&val_addr1).is_none());
+        assert!(table.jailed_validators(&store).is_empty());
+        assert!(table.idx_keypackage.values().all(|addr| *addr != addr1));
+        let staking = store.get(&addr1).unwrap();
+        assert!(staking.is_permanently_jailed());
+        assert_eq!(staking.bonded, Coin::zero());
+
+        let node_join = NodeJoinRequestTx {
+            nonce: staking.nonce,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(1 + 10 + 2, 5.into(), 10),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::IsJailed))
+        ));
+        // the rebuilt indexes don't include the cleaned up validator
+        assert!(table
+            .rebuild_index(&store)
+            .lookup_address(&val_addr1)
+            .is_none());
+    }
+
+    #[test]
+    fn check_jail_escalation() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_jail_escalation(100);
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let slash_ratio: SlashRatio = "0.1".parse().unwrap();
+        let jail_duration = info.get_unbonding_period();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let mut jail_unjail = |store: &mut StakingMemStore, block_time: Timespec, nonce| {
+            table.slash(
+                store,
+                &BeginBlockInfo { block_time, ..info },
+                &addr1,
+                slash_ratio,
+                PunishmentKind::NonLive,
+            );
+            let jailed_until = table.jailed_until(&*store, &addr1).unwrap();
+            let tx = UnjailTx {
+                nonce,
+                address: addr1,
+                attributes: Default::default(),
+            };
+            assert!(matches!(
+                table.unjail(
+                    store,
+                    &BlockContext::new(jailed_until - 1, 0.into(), 0),
+                    &tx
+                ),
+                Err(PublicTxError::Unjail(UnjailError::JailTimeNotExpired))
+            ));
+            table
+                .unjail(store, &BlockContext::new(jailed_until, 0.into(), 0), &tx)
+                .unwrap();
+            jailed_until
+        };
+
+        // first jailing
+        let jailed_until = jail_unjail(&mut store, 1, 0);
+        assert_eq!(jailed_until, 1 + jail_duration);
+
+        // jailed again within the grace period, twice as long
+        let block_time = jailed_until + 99;
+        let jailed_until = jail_unjail(&mut store, block_time, 1);
+        assert_eq!(jailed_until, block_time + 2 * jail_duration);
+
+        // jailed after the grace period, reset
+        let block_time = jailed_until + 100;
+        let jailed_until = jail_unjail(&mut store, block_time, 2);
+        assert_eq!(jailed_until, block_time + jail_duration);
+        match store.get(&addr1).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => assert_eq!(val.consecutive_jail_count, 1),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn check_unjail_not_jailed() {
+        let (mut table, mut store) = init_staking_table();
+        let unjail = |address| UnjailTx {
+            nonce: 0,
+            address,
+            attributes: Default::default(),
+        };
+
+        // not a validator
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(1, 0.into(), 0),
+                &unjail(addr4)
+            ),
+            Err(PublicTxError::Unjail(UnjailError::NotAValidator))
+        ));
+
+        // active validator
+        let addr1 = staking_address(&[0xcc; 32]);
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(1, 0.into(), 0),
+                &unjail(addr1)
+            ),
+            Err(PublicTxError::Unjail(UnjailError::NotJailed))
+        ));
+    }
+
+    #[test]
+    fn check_unjail_by_delegate() {
+        let (mut table, mut store) = init_staking_table();
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let addr1 = staking_address(&[0xcc; 32]);
+        let delegate = staking_address(&[0xd0; 32]);
+        let other = staking_address(&[0xd1; 32]);
+        let mut tx = UnjailTx {
+            nonce: 0,
+            address: addr1,
+            attributes: Default::default(),
+        };
+
+        // no delegate authorized
+        assert!(matches!(
+            table.unjail_signed_by(
+                &mut store,
+                &BlockContext::new(1, 0.into(), 0),
+                &tx,
+                &delegate
+            ),
+            Err(PublicTxError::StakingWitnessNotMatch)
+        ));
+
+        // authorize the delegate before jailed
+        let mut edit = EditValidatorTx {
+            nonce: 0,
+            address: addr1,
+            attributes: Default::default(),
+            name: "no-name".to_owned(),
+            security_contact: None,
+            consensus_pubkey: validator_pubkey(&[0xcc; 32]),
+            authorized_delegate: Some(delegate),
+        };
+        table.edit_validator(&mut store, &edit).unwrap();
+        table.slash(
+            &mut store,
+            &info,
+            &addr1,
+            "0.1".parse().unwrap(),
+            PunishmentKind::NonLive,
+        );
+        let jailed_until = table.jailed_until(&store, &addr1).unwrap();
+        tx.nonce = 1;
+
+        assert!(matches!(
+            table.unjail_signed_by(
+                &mut store,
+                &BlockContext::new(jailed_until, 0.into(), 0),
+                &tx,
+                &other
+            ),
+            Err(PublicTxError::StakingWitnessNotMatch)
+        ));
+        assert!(table.jailed_until(&store, &addr1).is_some());
+        table
+            .unjail_signed_by(
+                &mut store,
+                &BlockContext::new(jailed_until, 0.into(), 0),
+                &tx,
+                &delegate,
+            )
+            .unwrap();
+        let staking = store.get(&addr1).unwrap();
+        assert!(!staking.is_jailed());
+        assert_eq!(staking.nonce, 2);
+
+        // revoke the delegate
+        edit.nonce = 2;
+        edit.authorized_delegate = None;
+        table.edit_validator(&mut store, &edit).unwrap();
+        tx.nonce = 3;
+        assert!(matches!(
+            table.unjail_signed_by(
+                &mut store,
+                &BlockContext::new(jailed_until, 0.into(), 0),
+                &tx,
+                &delegate
+            ),
+            Err(PublicTxError::StakingWitnessNotMatch)
+        ));
+    }
+
+    #[test]
+    fn check_idempotent_unjail() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_idempotent_unjail(10);
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let addr1 = staking_address(&[0xcc; 32]);
+        table.slash(
+            &mut store,
+            &info,
+            &addr1,
+            "0.1".parse().unwrap(),
+            PunishmentKind::NonLive,
+        );
+        // unjailed some time after the jail expired
+        let unjailed_at = table.jailed_until(&store, &addr1).unwrap() + 5;
+        let tx = UnjailTx {
+            nonce: 0,
+            address: addr1,
+            attributes: Default::default(),
+        };
+        table
+            .unjail(
+                &mut store,
+                &BlockContext::new(unjailed_at, 0.into(), 0),
+                &tx,
+            )
+            .unwrap();
+        let staking = store.get(&addr1).unwrap();
+
+        // retried within the window since the unjail, no-op
+        table
+            .unjail(
+                &mut store,
+                &BlockContext::new(unjailed_at + 10, 0.into(), 0),
+                &tx,
+            )
+            .unwrap();
+        assert_eq!(store.get(&addr1).unwrap(), staking);
+
+        // other nonces are still rejected within the window
+        let future_tx = UnjailTx {
+            nonce: 2,
+            ..tx.clone()
+        };
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(unjailed_at + 1, 0.into(), 0),
+                &future_tx
+            ),
+            Err(PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 2
+            })
+        ));
+
+        // retried after the window
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(unjailed_at + 11, 0.into(), 0),
+                &tx
+            ),
+            Err(PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 0
+            })
+        ));
+
+        // strict if disabled
+        table.config.idempotent_unjail_window = None;
+        assert!(matches!(
+            table.unjail(
+                &mut store,
+                &BlockContext::new(unjailed_at + 1, 0.into(), 0),
+                &tx
+            ),
+            Err(PublicTxError::IncorrectNonce { .. })
+        ));
+    }
+
+    #[test]
+    fn check_edit_validator() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        let mut tx = EditValidatorTx {
+            nonce: 0,
+            address: addr1,
+            attributes: Default::default(),
+            name: "new name".to_owned(),
+            security_contact: Some("new@example.com".to_owned()),
+            consensus_pubkey: validator_pubkey(&[0xcc; 32]),
+            authorized_delegate: Some(addr4),
+        };
+        table.edit_validator(&mut store, &tx).unwrap();
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.nonce, 1);
+        match staking.node_meta {
+            Some(NodeState::CouncilNode(val)) => {
+                assert_eq!(val.council_node.node_info.name, "new name");
+                assert_eq!(
+                    val.council_node.node_info.security_contact,
+                    Some("new@example.com".to_owned())
+                );
+                assert_eq!(val.authorized_delegate, Some(addr4));
+                assert!(val.is_active());
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(table.active_validators(&store).len(), 3);
+
+        // can't change the consensus pubkey
+        tx.nonce = 1;
+        tx.consensus_pubkey = validator_pubkey(&[0xcf; 32]);
+        assert!(matches!(
+            table.edit_validator(&mut store, &tx),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::ConsensusKeyImmutable
+            ))
+        ));
+        assert_eq!(store.get(&addr1).unwrap().nonce, 1);
+
+        // not a council node
+        tx.address = addr4;
+        tx.nonce = 0;
+        assert!(matches!(
+            table.edit_validator(&mut store, &tx),
+            Err(PublicTxError::NodeJoin(NodeJoinError::NotCouncilNode))
+        ));
+    }
+
+    #[test]
+    fn check_reactivate() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+        let addr3 = staking_address(&[0xce; 32]);
+        let is_active = |store: &StakingMemStore, addr| match store.get(addr).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => val.is_active(),
+            _ => unreachable!(),
+        };
+
+        // already active
+        assert!(matches!(
+            table.reactivate(&mut store, &addr2, 0),
+            Err(PublicTxError::NodeJoin(NodeJoinError::AlreadyJoined))
+        ));
+
+        // deactivated by unbonding all
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(11_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert!(!is_active(&store, &addr1));
+        assert!(matches!(
+            table.reactivate(&mut store, &addr1, 1),
+            Err(PublicTxError::NodeJoin(NodeJoinError::BondedNotEnough))
+        ));
+        table
+            .deposit(&mut store, 0, &addr1, Coin::new(11_0000_0000).unwrap())
+            .unwrap();
+        assert!(matches!(
+            table.reactivate(&mut store, &addr1, 0),
+            Err(PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 0
+            })
+        ));
+        table.reactivate(&mut store, &addr1, 1).unwrap();
+        assert!(is_active(&store, &addr1));
+        assert_eq!(store.get(&addr1).unwrap().nonce, 2);
+
+        // jailed
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        table.slash(
+            &mut store,
+            &info,
+            &addr3,
+            "0.1".parse().unwrap(),
+            PunishmentKind::NonLive,
+        );
+        assert!(matches!(
+            table.reactivate(&mut store, &addr3, 0),
+            Err(PublicTxError::NodeJoin(NodeJoinError::IsJailed))
+        ));
+    }
+
+    #[test]
+    fn check_reactivate_min_self_bonded() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        let val_pk4 = validator_pubkey(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk4),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        match store.get(&addr4).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => {
+                assert_eq!(val.min_self_bonded, Coin::new(10_0000_0000).unwrap())
+            }
+            _ => unreachable!(),
+        }
+
+        // the minimal required staking is lowered, but the floor is kept
+        table.minimal_required_staking = Coin::new(5_0000_0000).unwrap();
+        let unbond = UnbondTx {
+            from_staked_account: addr4,
+            nonce: 1,
+            value: Coin::new(10_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(10, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(8_0000_0000).unwrap())
+            .unwrap();
+        assert!(matches!(
+            table.reactivate(&mut store, &addr4, 2),
+            Err(PublicTxError::NodeJoin(NodeJoinError::SelfBondedNotEnough))
+        ));
+
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(2_0000_0000).unwrap())
+            .unwrap();
+        table.reactivate(&mut store, &addr4, 2).unwrap();
+    }
+
+    #[test]
+    fn check_get_account() {
+        let (table, store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        assert_eq!(table.get_account(&store, &addr1), store.get(&addr1));
+        let staking = table.get_account(&store, &addr1).unwrap();
+        assert_eq!(staking.nonce, 0);
+        assert_eq!(staking.bonded, Coin::new(11_0000_0000).unwrap());
+
+        let addr4 = staking_address(&[0xcf; 32]);
+        assert_eq!(table.get_account(&store, &addr4), None);
+        // no side effect
+        assert_eq!(store.get(&addr4), None);
+    }
+
+    #[test]
+    fn check_verify_invariants() {
+        let (table, mut store) = init_staking_table();
+        assert_eq!(table.verify_invariants(&store), Ok(()));
+
+        // bonded amount changed behind the table's back
+        let addr1 = staking_address(&[0xcc; 32]);
+        let mut staking = store.get(&addr1).unwrap();
+        staking.bonded = Coin::new(20_0000_0000).unwrap();
+        store.set_staking(staking);
+        assert_eq!(
+            table.verify_invariants(&store),
+            Err(InvariantViolation::VotingPowerMismatch(addr1))
+        );
+
+        // staking removed from the heap
+        let (table, mut store) = init_staking_table();
+        store.0.remove(&addr1);
+        assert_eq!(
+            table.verify_invariants(&store),
+            Err(InvariantViolation::IndexDesync(addr1))
+        );
+    }
+
+    #[test]
+    fn check_verify_invariants_full() {
+        let (table, mut store) = init_staking_table();
+        assert_eq!(table.verify_invariants_full(&store), vec![]);
+
+        // bonded amount changed behind the table's back
+        let addr1 = staking_address(&[0xcc; 32]);
+        let mut staking = store.get(&addr1).unwrap();
+        staking.bonded = Coin::new(20_0000_0000).unwrap();
+        store.set_staking(staking);
+        // jailed but still active
+        let addr2 = staking_address(&[0xcd; 32]);
+        let mut staking = store.get(&addr2).unwrap();
+        if let Some(NodeState::CouncilNode(val)) = &mut staking.node_meta {
+            val.jailed_until = Some(10);
+            val.jail_reason = Some(JailReason::NonLive);
+        }
+        store.set_staking(staking);
+
+        assert_eq!(
+            table.verify_invariants_full(&store),
+            vec![
+                InvariantViolation::VotingPowerMismatch(addr1),
+                InvariantViolation::InvalidValidator(addr2),
+            ]
+        );
+        // only the first one
+        assert_eq!(
+            table.verify_invariants(&store),
+            Err(InvariantViolation::VotingPowerMismatch(addr1))
+        );
+    }
+
+    #[test]
+    fn check_active_validators() {
+        let (mut table, mut store) = init_staking_table();
+        let val_addr = |seed: &[u8; 32]| TendermintValidatorAddress::from(&validator_pubkey(seed));
+        assert_eq!(
+            table.active_validators(&store),
+            vec![
+                (val_addr(&[0xce; 32]), TendermintVotePower::new(13).unwrap()),
+                (val_addr(&[0xcd; 32]), TendermintVotePower::new(12).unwrap()),
+                (val_addr(&[0xcc; 32]), TendermintVotePower::new(11).unwrap()),
+            ]
+        );
+
+        // equal power, order by validator address
+        let addr1 = staking_address(&[0xcc; 32]);
+        table
+            .deposit(&mut store, 0, &addr1, Coin::new(2_0000_0000).unwrap())
+            .unwrap();
+        let mut expected = vec![
+            (val_addr(&[0xcc; 32]), TendermintVotePower::new(13).unwrap()),
+            (val_addr(&[0xce; 32]), TendermintVotePower::new(13).unwrap()),
+        ];
+        expected.sort();
+        expected.push((val_addr(&[0xcd; 32]), TendermintVotePower::new(12).unwrap()));
+        assert_eq!(table.active_validators(&store), expected);
+    }
+
+    #[test]
+    fn check_iter_accounts() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        for _ in 0..2 {
+            table
+                .deposit(&mut store, 0, &addr4, Coin::new(1).unwrap())
+                .unwrap();
+        }
+
+        let accounts = table.iter_accounts(&store).collect::<Vec<_>>();
+        assert_eq!(accounts.len(), 4);
+        let mut addresses = [0xcc, 0xcd, 0xce, 0xcf]
+            .iter()
+            .map(|seed| staking_address(&[*seed; 32]))
+            .collect::<Vec<_>>();
+        addresses.sort();
+        assert_eq!(
+            accounts.iter().map(|(addr, _)| *addr).collect::<Vec<_>>(),
+            addresses
+        );
+        for (addr, staking) in accounts.into_iter() {
+            assert_eq!(store.get(&addr), Some(staking));
+        }
+    }
+
+    #[test]
+    fn check_deposit_account_unbonding() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let amount = Coin::new(1_0000_0000).unwrap();
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: amount,
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+
+        // accepted with a warning, including the dry run
+        let event = table.validate_deposit(&store, 5, &addr1, amount).unwrap();
+        assert_eq!(event.unbonding_until, Some(10));
+        assert_eq!(table.deposit(&mut store, 5, &addr1, amount).unwrap(), event);
+
+        // until the latest unbonded coins are withdrawable
+        let unbond = UnbondTx { nonce: 1, ..unbond };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(3, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        let event = table.deposit(&mut store, 10, &addr1, amount).unwrap();
+        assert_eq!(event.unbonding_until, Some(13));
+        let event = table.deposit(&mut store, 13, &addr1, amount).unwrap();
+        assert_eq!(event.unbonding_until, None);
+    }
+
+    #[test]
+    fn check_total_staked() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        assert_eq!(
+            table.total_staked(&store).unwrap(),
+            (Coin::new(36_0000_0000).unwrap(), Coin::zero())
+        );
+
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(1_0000_0000).unwrap())
+            .unwrap();
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: Coin::new(2_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        table
+            .withdraw_partial(
+                &mut store,
+                &BlockContext::new(10, 0.into(), 0),
+                &addr1,
+                Coin::new(1_0000_0000).unwrap(),
+            )
+            .unwrap();
+
+        let (bonded, unbonded) = table.total_staked(&store).unwrap();
+        assert_eq!(bonded, Coin::new(35_0000_0000).unwrap());
+        assert_eq!(unbonded, Coin::new(1_0000_0000).unwrap());
+        // matches the sum over all accounts
+        let accounts = table.iter_accounts(&store).collect::<Vec<_>>();
+        assert_eq!(
+            bonded,
+            sum_coins(accounts.iter().map(|(_, staking)| staking.bonded)).unwrap()
+        );
+        assert_eq!(
+            unbonded,
+            sum_coins(accounts.iter().map(|(_, staking)| staking.unbonded)).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_deposit_existing() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        let amount = Coin::new(1_0000_0000).unwrap();
+
+        // strict variant doesn't create the account
+        assert!(matches!(
+            table.deposit_existing(&mut store, 0, &addr4, amount),
+            Err(DepositError::AccountNotFound)
+        ));
+        assert!(store.get(&addr4).is_none());
+        table
+            .deposit_existing(&mut store, 0, &addr1, amount)
+            .unwrap();
+        assert_eq!(
+            store.get(&addr1).unwrap().bonded,
+            Coin::new(12_0000_0000).unwrap()
+        );
+
+        // creating variant
+        table.deposit(&mut store, 0, &addr4, amount).unwrap();
+        assert_eq!(store.get(&addr4).unwrap().bonded, amount);
+        table
+            .deposit_existing(&mut store, 0, &addr4, amount)
+            .unwrap();
+        assert_eq!(
+            store.get(&addr4).unwrap().bonded,
+            Coin::new(2_0000_0000).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_deposit_batch() {
+        let (mut table, mut store) = init_staking_table();
+        let (mut table2, mut store2) = (table.clone(), store.clone());
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        let deposits = [
+            (addr1, Coin::new(1_0000_0000).unwrap()),
+            (addr4, Coin::new(2_0000_0000).unwrap()),
+            (addr1, Coin::new(3_0000_0000).unwrap()),
+        ];
+
+        table.deposit_batch(&mut store, &deposits).unwrap();
+        for (addr, amount) in deposits.iter() {
+            table2.deposit(&mut store2, 0, addr, *amount).unwrap();
+        }
+        assert_eq!(store.0, store2.0);
+        assert_eq!(
+            table.active_validators(&store),
+            table2.active_validators(&store2)
+        );
+        assert_eq!(
+            store.get(&addr1).unwrap().bonded,
+            Coin::new(15_0000_0000).unwrap()
+        );
+
+        // nothing is applied if any deposit fails
+        let addr5 = staking_address(&[0xd0; 32]);
+        let deposits = [(addr5, Coin::new(1).unwrap()), (addr1, Coin::max())];
+        assert!(matches!(
+            table.deposit_batch(&mut store, &deposits),
+            Err(DepositError::InBatch { address, .. }) if address == addr1
+        ));
+        assert_eq!(store.get(&addr5), None);
+        assert_eq!(store.0, store2.0);
+    }
+
+    #[test]
+    fn check_voting_power() {
+        let (table, store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(
+            table.voting_power(&staking),
+            Coin::new(11_0000_0000).unwrap().into()
+        );
+
+        // exactly minimal required staking
+        let mut staking = new_validator(&[0xcf; 32], Coin::new(10_0000_0000).unwrap());
+        assert_eq!(
+            table.voting_power(&staking),
+            TendermintVotePower::new(10).unwrap()
+        );
+        // below minimal required staking
+        staking.bonded = (staking.bonded - Coin::unit()).unwrap();
+        assert_eq!(table.voting_power(&staking), TendermintVotePower::zero());
+
+        // jailed
+        let mut staking = new_validator(&[0xcf; 32], Coin::new(10_0000_0000).unwrap());
+        if let Some(NodeState::CouncilNode(val)) = &mut staking.node_meta {
+            val.jail(0, BlockHeight::genesis(), 10, JailReason::NonLive, None);
+        }
+        assert_eq!(table.voting_power(&staking), TendermintVotePower::zero());
+
+        // not a validator
+        let mut staking = StakedState::default(staking_address(&[0xcf; 32]));
+        staking.bonded = Coin::new(10_0000_0000).unwrap();
+        assert_eq!(table.voting_power(&staking), TendermintVotePower::zero());
+
+        // smaller unit
+        let table = table.with_voting_power_unit(1_0000);
+        let staking = new_validator(&[0xcf; 32], Coin::new(10_0000_0000).unwrap());
+        assert_eq!(
+            table.voting_power(&staking),
+            TendermintVotePower::new(10_0000).unwrap()
+        );
+    }
+
+    #[test]
+    fn check_end_block_voting_power_unit() {
+        let (table, store) = init_staking_table();
+        let mut table = table.with_voting_power_unit(1_0000);
+        // the genesis validators are chosen with the default unit, all updated in the end block
+        let mut updates = table.end_block(&store, 3);
+        updates.sort_by_key(|(_, power)| *power);
+        assert_eq!(
+            updates,
+            vec![
+                (
+                    validator_pubkey(&[0xcc; 32]),
+                    TendermintVotePower::new(11_0000).unwrap()
+                ),
+                (
+                    validator_pubkey(&[0xcd; 32]),
+                    TendermintVotePower::new(12_0000).unwrap()
+                ),
+                (
+                    validator_pubkey(&[0xce; 32]),
+                    TendermintVotePower::new(13_0000).unwrap()
+                ),
+            ]
+        );
+        // the queries agree with the voting powers sent to tendermint
+        for (addr, power) in table.get_chosen_validators().iter() {
+            assert_eq!(*power, table.voting_power(&store.get(addr).unwrap()));
+        }
+        let mut active = table
+            .active_validators(&store)
+            .into_iter()
+            .map(|(_, power)| power)
+            .collect::<Vec<_>>();
+        active.sort();
+        assert_eq!(
+            active,
+            updates
+                .into_iter()
+                .map(|(_, power)| power)
+                .collect::<Vec<_>>()
+        );
+        assert!(table.end_block(&store, 3).is_empty());
+    }
+
+    #[test]
+    fn check_validators_unjailable_at() {
+        let (table, mut store) = init_staking_table();
+        // jailed permanently for byzantine fault if `duration` is `None`
+        let jail = |store: &mut StakingMemStore, seed: u8, duration: Option<Timespec>| {
+            let mut staking = store.get(&staking_address(&[seed; 32])).unwrap();
+            if let Some(NodeState::CouncilNode(val)) = &mut staking.node_meta {
+                match duration {
+                    Some(duration) => {
+                        val.jail(
+                            0,
+                            BlockHeight::genesis(),
+                            duration,
+                            JailReason::NonLive,
+                            None,
+                        );
+                    }
+                    None => {
+                        val.jail_permanently(0, BlockHeight::genesis(), JailReason::ByzantineFault);
+                    }
+                }
+            }
+            store.set_staking(staking);
+        };
+        jail(&mut store, 0xcc, Some(10));
+        jail(&mut store, 0xcd, Some(20));
+        jail(&mut store, 0xce, None);
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+
+        assert_eq!(table.validators_unjailable_at(&store, 9), vec![]);
+        assert_eq!(table.validators_unjailable_at(&store, 15), vec![addr1]);
+        let mut expected = vec![addr1, addr2];
+        expected.sort();
+        assert_eq!(table.validators_unjailable_at(&store, 20), expected);
+    }
+
+    #[test]
+    fn check_jailed_validators() {
+        let (mut table, mut store) = init_staking_table();
+        // jailed permanently for byzantine fault if `duration` is `None`
+        let jail = |store: &mut StakingMemStore, seed: u8, duration: Option<Timespec>| {
+            let mut staking = store.get(&staking_address(&[seed; 32])).unwrap();
+            if let Some(NodeState::CouncilNode(val)) = &mut staking.node_meta {
+                match duration {
+                    Some(duration) => {
+                        val.jail(
+                            0,
+                            BlockHeight::genesis(),
+                            duration,
+                            JailReason::NonLive,
+                            None,
+                        );
+                    }
+                    None => {
+                        val.jail_permanently(0, BlockHeight::genesis(), JailReason::ByzantineFault);
+                    }
+                }
+            }
+            store.set_staking(staking);
+        };
+        assert_eq!(table.jailed_validators(&store), vec![]);
+
+        // 0xcd stays active
+        jail(&mut store, 0xcc, Some(10));
+        jail(&mut store, 0xce, None);
+        // non-validator account
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(1_0000_0000).unwrap())
+            .unwrap();
+
+        let jailed_until = |addr| table.jailed_until(&store, addr).unwrap();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr3 = staking_address(&[0xce; 32]);
+        let mut expected = vec![(addr1, jailed_until(&addr1)), (addr3, jailed_until(&addr3))];
+        expected.sort();
+        assert_eq!(table.jailed_validators(&store), expected);
+    }
+
+    #[test]
+    fn check_jailed_until() {
+        let (mut table, mut store) = init_staking_table();
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(1_0000_0000).unwrap())
+            .unwrap();
+
+        table.slash(
+            &mut store,
+            &info,
+            &addr1,
+            "0.1".parse().unwrap(),
+            PunishmentKind::NonLive,
+        );
+        let staking = store.get(&addr1).unwrap();
+        let expected = staking.node_meta.as_ref().and_then(|meta| match meta {
+            NodeState::CouncilNode(val) => val.jailed_until,
+            _ => None,
+        });
+        assert!(expected.is_some());
+        assert_eq!(table.jailed_until(&store, &addr1), expected);
+        // pure read
+        assert_eq!(store.get(&addr1).unwrap(), staking);
+
+        // active validator
+        assert_eq!(table.jailed_until(&store, &addr2), None);
+        // not a validator
+        assert_eq!(table.jailed_until(&store, &addr4), None);
+        // not exists
+        assert_eq!(
+            table.jailed_until(&store, &staking_address(&[0xd0; 32])),
+            None
+        );
+    }
+
+    #[test]
+    fn check_slash() {
+        let (mut table, mut store) = init_staking_table();
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let slash_ratio: SlashRatio = "0.1".parse().unwrap();
+
+        // all bonded
+        let addr1 = staking_address(&[0xcc; 32]);
+        let burned = table
+            .slash(
+                &mut store,
+                &info,
+                &addr1,
+                slash_ratio,
+                PunishmentKind::ByzantineFault,
+            )
+            .unwrap();
+        assert_eq!(burned, Coin::new(1_1000_0000).unwrap());
+        let staking = store.get(&addr1).unwrap();
+        assert_eq!(staking.bonded, Coin::new(9_9000_0000).unwrap());
+        assert_eq!(staking.unbonded, Coin::zero());
+        assert_eq!(staking.last_slash.unwrap().amount, burned);
+        assert!(staking.is_jailed());
+
+        // all unbonded
+        let addr2 = staking_address(&[0xcd; 32]);
+        let unbond = UnbondTx {
+            from_staked_account: addr2,
+            nonce: 0,
+            value: Coin::new(12_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(1, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        let burned = table
+            .slash(
+                &mut store,
+                &info,
+                &addr2,
+                slash_ratio,
+                PunishmentKind::NonLive,
+            )
+            .unwrap();
+        assert_eq!(burned, Coin::new(1_2000_0000).unwrap());
+        let staking = store.get(&addr2).unwrap();
+        assert_eq!(staking.bonded, Coin::zero());
+        assert_eq!(staking.unbonded, Coin::new(10_8000_0000).unwrap());
+        assert!(staking.is_jailed());
+
+        // mixed
+        let addr3 = staking_address(&[0xce; 32]);
+        let unbond = UnbondTx {
+            from_staked_account: addr3,
+            nonce: 0,
+            value: Coin::new(3_0000_0000).unwrap(),
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                &mut store,
+                &BlockContext::new(1, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        let burned = table
+            .slash(
+                &mut store,
+                &info,
+                &addr3,
+                slash_ratio,
+                PunishmentKind::ByzantineFault,
+            )
+            .unwrap();
+        assert_eq!(burned, Coin::new(1_3000_0000).unwrap());
+        let staking = store.get(&addr3).unwrap();
+        assert_eq!(staking.bonded, Coin::new(9_0000_0000).unwrap());
+        assert_eq!(staking.unbonded, Coin::new(2_7000_0000).unwrap());
+        assert!(staking.is_jailed());
+
+        // all jailed validators are removed from validator set
+        assert_eq!(table.end_block(&store, 3).len(), 3);
+        assert!(table.get_chosen_validators().is_empty());
+
+        // not exists, nothing is written
+        let addr4 = staking_address(&[0xcf; 32]);
+        assert_eq!(
+            table.slash(
+                &mut store,
+                &info,
+                &addr4,
+                slash_ratio,
+                PunishmentKind::NonLive
+            ),
+            None
+        );
+        assert!(store.get(&addr4).is_none());
+    }
+
+    fn unbond_deposit_rejoin(
+        table: &mut StakingTable,
+        store: &mut impl StoreStaking,
+        addr: StakedStateAddress,
+        amount: Coin,
+        val_pk_new: TendermintValidatorPubKey,
+    ) -> Result<(), PublicTxError> {
+        // unbond/deposit/re-join
+        let staking = store.get(&addr).unwrap();
+        let val_pk = match &staking.node_meta {
+            Some(NodeState::CouncilNode(v)) => &v.council_node.consensus_pubkey,
+            _ => unreachable!(),
+        };
+        let unbond = UnbondTx {
+            from_staked_account: addr,
+            nonce: staking.nonce,
+            value: amount,
+            attributes: Default::default(),
+        };
+        table
+            .unbond(
+                store,
+                &BlockContext::new(0, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        assert_eq!(
+            table.end_block(&*store, 3),
+            vec![(val_pk.clone(), Coin::zero().into())]
+        );
+        table.deposit(store, 0, &addr, amount).unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: staking.nonce + 1,
+            address: addr,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk_new.clone()),
+        };
+        // change to new validator key
+        let result = table.node_join(store, &BlockContext::new(1, 0.into(), 1), 0, 0, &node_join);
+        if result.is_ok() {
+            let staking = store.get(&addr).unwrap();
+            assert_eq!(
+                table.end_block(&*store, 3),
+                vec![(val_pk_new, staking.bonded.into())]
+            );
+        }
+        result.map(|_| ())
+    }
+
+    #[test]
+    fn check_can_join() {
+        let (mut table, mut store) = init_staking_table();
+        let ctx = BlockContext::new(0, 0.into(), 0);
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(5_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        assert!(matches!(
+            table.can_join(&store, &ctx, &node_join),
+            Err(NodeJoinError::BondedNotEnough)
+        ));
+
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(5_0000_0000).unwrap())
+            .unwrap();
+        table.can_join(&store, &ctx, &node_join).unwrap();
+
+        // the validator address of 0xcc is taken
+        let node_join = NodeJoinRequestTx {
+            node_meta: mock_council_node(validator_pubkey(&[0xcc; 32])),
+            ..node_join
+        };
+        assert!(matches!(
+            table.can_join(&store, &ctx, &node_join),
+            Err(NodeJoinError::DuplicateValidatorAddress { owner })
+                if owner == staking_address(&[0xcc; 32])
+        ));
+
+        // nothing is modified
+        assert!(!store.get(&addr4).unwrap().has_council_node_meta());
+        assert_eq!(table.idx_validator_address.len(), 3);
+    }
+
+    #[cfg(not(feature = "mock-enclave"))]
+    #[test]
+    fn check_keypackage_already_registered() {
+        // time when the keypackage test vector is valid
+        let block_time = 1590490084;
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+
+        // genesis validators are registered with the same keypackage
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(block_time, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::KeyPackageAlreadyRegistered
+            ))
+        ));
+        assert!(!store.get(&addr4).unwrap().has_council_node_meta());
+    }
+
+    #[cfg(not(feature = "mock-enclave"))]
+    #[test]
+    fn check_node_join_empty_keypackage() {
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: NodeMetadata::new_council_node_with_details(
+                "no-name".to_string(),
+                None,
+                validator_pubkey(&[0xcf; 32]),
+                chain_core::state::account::ConfidentialInit { keypackage: vec![] },
+            ),
+        };
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(0, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(NodeJoinError::EmptyKeyPackage))
+        ));
+        assert!(!store.get(&addr4).unwrap().has_council_node_meta());
+    }
+
+    /// Accept the keypackage test vector, reporting it as the latest enclave version
+    #[cfg(not(feature = "mock-enclave"))]
+    #[derive(Clone)]
+    struct StubVerifier;
+
+    #[cfg(not(feature = "mock-enclave"))]
+    impl ra_client::AttestedCertVerifier for StubVerifier {
+        fn verify_attested_cert(
+            &self,
+            certificate: &[u8],
+            now: chrono::DateTime<chrono::Utc>,
+        ) -> Result<ra_client::CertVerifyResult, ra_client::EnclaveCertVerifierError> {
+            let mut result =
+                ra_client::ENCLAVE_CERT_VERIFIER.verify_attested_cert(certificate, now)?;
+            result.quote.report_body.isv_svn = u16::max_value();
+            Ok(result)
+        }
+    }
+
+    #[cfg(not(feature = "mock-enclave"))]
+    #[test]
+    fn check_node_join_with_verifier() {
+        // time when the keypackage test vector is valid
+        let block_time = 1590490084;
+        let (mut table, mut store) = init_staking_table();
+        // genesis validators are registered with the same keypackage
+        table.idx_keypackage.clear();
+        let addr4 = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr4,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
+        };
+        let result = table
+            .node_join_with_verifier(
+                &mut store,
+                &BlockContext::new(block_time, 0.into(), 0),
+                0,
+                0,
+                &node_join,
+                &StubVerifier,
+            )
+            .unwrap();
+        assert_eq!(result.isv_svn, u16::max_value());
+        assert!(store.get(&addr4).unwrap().has_council_node_meta());
+    }
+
+    #[test]
+    fn check_used_validator_key() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let val_pk1 = validator_pubkey(&[0xcc; 32]);
+
+        for i in 0..10 {
+            let val_pk_new = validator_pubkey(&[0x00 + i; 32]);
+            unbond_deposit_rejoin(
+                &mut table,
+                &mut store,
+                addr1,
+                Coin::new(11_0000_0000).unwrap(),
+                val_pk_new,
+            )
+            .unwrap();
+        }
+
+        // exceed max used validator address
+        let val_pk_new = validator_pubkey(&[0x00 + 10; 32]);
+        assert!(matches!(
+            unbond_deposit_rejoin(
+                &mut table,
+                &mut store,
+                addr1,
+                Coin::new(11_0000_0000).unwrap(),
+                val_pk_new,
+            ),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::UsedValidatorAddrFull { .. }
+            ))
+        ));
+
+        let addr_new = staking_address(&[0xcf; 32]);
+        table
+            .deposit(&mut store, 0, &addr_new, Coin::new(10_0000_0000).unwrap())
+            .unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: 0,
+            address: addr_new,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk1),
+        };
+        // can't join with used key
+        assert!(matches!(
+            table.node_join(
+                &mut store,
+                &BlockContext::new(1, 0.into(), 0),
+                0,
+                0,
+                &node_join
+            ),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::DuplicateValidatorAddress { owner }
+            )) if owner == addr1
+        ));
+    }
+
+    #[test]
+    fn check_validator_address_owned_by_other_account() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+        let amount = Coin::new(11_0000_0000).unwrap();
+
+        // re-join with the consensus key of another active validator
+        assert!(matches!(
+            unbond_deposit_rejoin(
+                &mut table,
+                &mut store,
+                addr1,
+                amount,
+                validator_pubkey(&[0xcd; 32])
+            ),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::DuplicateValidatorAddress { owner }
+            )) if owner == addr2
+        ));
+    }
+
+    #[test]
+    fn check_max_used_validator_addr() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_max_used_validator_addr(2);
+        let addr1 = staking_address(&[0xcc; 32]);
+        let amount = Coin::new(11_0000_0000).unwrap();
+
+        for i in 0..2 {
+            let val_pk_new = validator_pubkey(&[i; 32]);
+            unbond_deposit_rejoin(&mut table, &mut store, addr1, amount, val_pk_new).unwrap();
+        }
+
+        // exceed max used validator address
+        let val_pk_new = validator_pubkey(&[2; 32]);
+        assert!(matches!(
+            unbond_deposit_rejoin(&mut table, &mut store, addr1, amount, val_pk_new.clone()),
+            Err(PublicTxError::NodeJoin(
+                NodeJoinError::UsedValidatorAddrFull { .. }
+            ))
+        ));
+
+        // the used addresses are out of date, so there's room again
+        let node_join = NodeJoinRequestTx {
+            nonce: store.get(&addr1).unwrap().nonce,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk_new),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(1 + 1, 0.into(), 1),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+        match store.get(&addr1).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => {
+                assert_eq!(val.used_validator_addresses.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn check_used_validator_addr_retry_after() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_max_used_validator_addr(2);
+        let addr1 = staking_address(&[0xcc; 32]);
+        let amount = Coin::new(11_0000_0000).unwrap();
+        let max_evidence_age = 10;
+
+        // used at block time 1
+        unbond_deposit_rejoin(
+            &mut table,
+            &mut store,
+            addr1,
+            amount,
+            validator_pubkey(&[0; 32]),
+        )
+        .unwrap();
+        // unbond/deposit/re-join at `block_time`
+        let rejoin = |table: &mut StakingTable,
+                      store: &mut StakingMemStore,
+                      block_time: Timespec,
+                      val_pk_new: TendermintValidatorPubKey| {
+            let unbond = UnbondTx {
+                from_staked_account: addr1,
+                nonce: store.get(&addr1).unwrap().nonce,
+                value: amount,
+                attributes: Default::default(),
+            };
+            table
+                .unbond(
+                    store,
+                    &BlockContext::new(block_time, 1.into(), max_evidence_age),
+                    &unbond,
+                    Fee::zero(),
+                )
+                .unwrap();
+            table.end_block(&*store, 3);
+            table.deposit(store, 0, &addr1, amount).unwrap();
+            let node_join = NodeJoinRequestTx {
+                nonce: store.get(&addr1).unwrap().nonce,
+                address: addr1,
+                attributes: Default::default(),
+                node_meta: mock_council_node(val_pk_new),
+            };
+            table.node_join(
+                store,
+                &BlockContext::new(block_time, 0.into(), max_evidence_age),
+                0,
+                0,
+                &node_join,
+            )
+        };
+        // used at block time 5
+        rejoin(&mut table, &mut store, 5, validator_pubkey(&[1; 32])).unwrap();
+
+        // the oldest used address (block time 1) gets out of date first
+        let val_pk_new = validator_pubkey(&[2; 32]);
+        match rejoin(&mut table, &mut store, 6, val_pk_new.clone()) {
+            Err(PublicTxError::NodeJoin(NodeJoinError::UsedValidatorAddrFull { retry_after })) => {
+                assert_eq!(retry_after, 1 + max_evidence_age)
+            }
+            _ => unreachable!(),
+        }
+
+        // accepted at `retry_after`
+        let node_join = NodeJoinRequestTx {
+            nonce: store.get(&addr1).unwrap().nonce,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(val_pk_new),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(1 + max_evidence_age, 0.into(), max_evidence_age),
+                0,
+                0,
+                &node_join,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn check_rebuild_index() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        // keep a used validator address in the validator record
+        unbond_deposit_rejoin(
+            &mut table,
+            &mut store,
+            addr1,
+            Coin::new(11_0000_0000).unwrap(),
+            validator_pubkey(&[0; 32]),
+        )
+        .unwrap();
+        let expected = table.clone();
+
+        // corrupt the indexes
+        let val_addr1 = TendermintValidatorAddress::from(&validator_pubkey(&[0xcc; 32]));
+        table.idx_validator_address.remove(&val_addr1);
+        table.idx_validator_address.insert(
+            TendermintValidatorAddress::from(&validator_pubkey(&[0xdd; 32])),
+            addr1,
+        );
+        table.idx_keypackage.clear();
+        assert!(table.verify_invariants(&store).is_err());
+
+        let table = table.rebuild_index(&store);
+        assert_eq!(table.verify_invariants(&store), Ok(()));
+        assert_eq!(table.idx_validator_address, expected.idx_validator_address);
+        assert_eq!(table.idx_validator_address.get(&val_addr1), Some(&addr1));
+        // the validators share the same mock keypackage
+        assert_eq!(table.idx_keypackage.len(), expected.idx_keypackage.len());
+    }
+
+    #[test]
+    fn check_initialize() {
+        use parity_scale_codec::{Decode, Encode};
+
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        // keep a used validator address in the validator record
+        unbond_deposit_rejoin(
+            &mut table,
+            &mut store,
+            addr1,
+            Coin::new(11_0000_0000).unwrap(),
+            validator_pubkey(&[0; 32]),
+        )
+        .unwrap();
+
+        // restored from the stored state, the used validator address is indexed
+        let mut restored = StakingTable::decode(&mut table.encode().as_slice()).unwrap();
+        restored.initialize(&store, table.minimal_required_staking, 50);
+        assert_eq!(restored.verify_invariants(&store), Ok(()));
+        assert_eq!(restored.idx_validator_address, table.idx_validator_address);
+        assert_eq!(
+            restored.lookup_address(&TendermintValidatorAddress::from(&validator_pubkey(
+                &[0xcc; 32]
+            ))),
+            Some(&addr1)
+        );
+        // the validators share the same mock keypackage
+        assert_eq!(restored.idx_keypackage.len(), table.idx_keypackage.len());
+    }
+
+    #[test]
+    fn check_initialize_rebuild_index() {
+        use parity_scale_codec::{Decode, Encode};
+
+        let (table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+        // corrupt the heap, the indexed addr2 is not a council node anymore
+        let mut staking = store.get(&addr2).unwrap();
+        staking.node_meta = None;
+        store.set_staking(staking);
+
+        // rebuilt rather than panic
+        let mut restored = StakingTable::decode(&mut table.encode().as_slice()).unwrap();
+        restored.initialize(&store, table.minimal_required_staking, 50);
+        assert_eq!(restored.verify_invariants(&store), Ok(()));
+        assert_eq!(restored.idx_validator_address.len(), 2);
+        assert_eq!(
+            restored.lookup_address(&TendermintValidatorAddress::from(&validator_pubkey(
+                &[0xcc; 32]
+            ))),
+            Some(&addr1)
+        );
+        assert_eq!(
+            restored.lookup_address(&TendermintValidatorAddress::from(&validator_pubkey(
+                &[0xcd; 32]
+            ))),
+            None
+        );
+    }
+
+    #[test]
+    fn check_rebuild_index_duplicate_validator_address() {
+        let (table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+        // corrupt the heap, both use the validator address of addr1
+        let mut staking = store.get(&addr2).unwrap();
+        staking.node_meta = store.get(&addr1).unwrap().node_meta;
+        store.set_staking(staking);
+
+        // the first one in the heap order is kept, not overwritten
+        let rebuilt = table.rebuild_index(&store);
+        let val_addr1 = TendermintValidatorAddress::from(&validator_pubkey(&[0xcc; 32]));
+        assert_eq!(
+            rebuilt.lookup_address(&val_addr1),
+            Some(std::cmp::min(&addr1, &addr2))
+        );
+        assert_eq!(rebuilt.idx_validator_address.len(), 2);
+    }
+
+    #[test]
+    fn check_snapshot_roundtrip() {
+        use parity_scale_codec::Encode;
+
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        // keep a used validator address in the validator record
+        unbond_deposit_rejoin(
+            &mut table,
+            &mut store,
+            addr1,
+            Coin::new(11_0000_0000).unwrap(),
+            validator_pubkey(&[0; 32]),
+        )
+        .unwrap();
+        let bytes = table.export_snapshot(&store);
+        assert_eq!(bytes[0], SNAPSHOT_VERSION);
+
+        let mut imported_store = StakingMemStore::new();
+        let imported = StakingTable::import_snapshot(&bytes, &mut imported_store).unwrap();
+        assert_eq!(imported.verify_invariants(&imported_store), Ok(()));
+        assert_eq!(imported.encode(), table.encode());
+        assert_eq!(
+            imported.minimal_required_staking,
+            table.minimal_required_staking
+        );
+        assert_eq!(imported.idx_validator_address, table.idx_validator_address);
+        for addr in table.idx_validator_address.values() {
+            assert_eq!(imported_store.get(addr), store.get(addr));
+        }
 
-        // transaction denied after jailed
-        let unbond = UnbondTx {
-            from_staked_account: addr1,
-            nonce,
-            value: staking.bonded,
-            attributes: Default::default(),
-        };
+        let mut bytes = bytes;
+        bytes[0] = SNAPSHOT_VERSION + 1;
         assert!(matches!(
-            table.unbond(&mut store, 10, 2, 3.into(), &unbond, Fee::zero()),
-            Err(PublicTxError::Unbond(UnbondError::IsJailed))
-        ));
-        assert!(matches!(
-            table.deposit(&mut store, &addr1, Coin::new(2_0000_0000).unwrap()),
-            Err(DepositError::IsJailed)
+            StakingTable::import_snapshot(&bytes, &mut StakingMemStore::new()),
+            Err(SnapshotError::UnsupportedVersion(_))
         ));
         assert!(matches!(
-            table.withdraw(&mut store, 0, &addr1, staking.unbonded),
-            Err(WithdrawError::IsJailed)
-        ));
-        let val_pk_new = validator_pubkey(&[0xcf; 32]);
-        let node_join = NodeJoinRequestTx {
-            nonce,
-            address: addr1,
-            attributes: Default::default(),
-            node_meta: mock_council_node(val_pk_new),
-        };
-        assert!(matches!(
-            table.node_join(&mut store, 3, 0, 0, &node_join),
-            Err(PublicTxError::NodeJoin(NodeJoinError::IsJailed))
+            StakingTable::import_snapshot(&[], &mut StakingMemStore::new()),
+            Err(SnapshotError::Empty)
         ));
-        // failed execution don't increase nonce
-        assert_eq!(store.get(&addr1).unwrap().nonce, nonce);
+    }
 
-        // unjail
-        let tx = UnjailTx {
-            nonce,
-            address: addr1,
-            attributes: Default::default(),
-        };
-        assert!(matches!(
-            table.unjail(&mut store, 1 + 1, &tx),
-            Err(PublicTxError::Unjail(UnjailError::JailTimeNotExpired))
-        ));
-        table.unjail(&mut store, 1 + 10, &tx).unwrap();
-        // unjail increase nonce by one
-        let staking = store.get(&addr1).unwrap();
-        assert_eq!(staking.nonce, nonce + 1);
-        assert!(!staking.is_jailed());
+    #[test]
+    fn check_used_addresses_canonical_order() {
+        use parity_scale_codec::Encode;
+
+        let val_addr = |seed: &[u8; 32]| TendermintValidatorAddress::from(&validator_pubkey(seed));
+        let (addr0, addr1, addr2) = (val_addr(&[0; 32]), val_addr(&[1; 32]), val_addr(&[2; 32]));
+
+        // same logical set reached by different insertion orders
+        let mut used1 = vec![(addr0.clone(), 1)];
+        tx::add_old_val_addr(&mut used1, 2, &addr2, 10, 100).unwrap();
+        tx::add_old_val_addr(&mut used1, 2, &addr1, 10, 100).unwrap();
+        let mut used2 = vec![(addr0, 1)];
+        tx::add_old_val_addr(&mut used2, 2, &addr1, 10, 100).unwrap();
+        tx::add_old_val_addr(&mut used2, 2, &addr2, 10, 100).unwrap();
+        assert_eq!(used1.encode(), used2.encode());
+        assert!(used1
+            .windows(2)
+            .all(|pair| (pair[0].1, &pair[0].0) < (pair[1].1, &pair[1].0)));
     }
 
-    fn unbond_deposit_rejoin(
-        table: &mut StakingTable,
-        store: &mut impl StoreStaking,
-        addr: StakedStateAddress,
-        amount: Coin,
-        val_pk_new: TendermintValidatorPubKey,
-    ) -> Result<(), PublicTxError> {
-        // unbond/deposit/re-join
-        let staking = store.get(&addr).unwrap();
-        let val_pk = match &staking.node_meta {
-            Some(NodeState::CouncilNode(v)) => &v.council_node.consensus_pubkey,
+    #[test]
+    fn check_prune_used_addresses() {
+        let (mut table, mut store) = init_staking_table();
+        let addr1 = staking_address(&[0xcc; 32]);
+        let amount = Coin::new(11_0000_0000).unwrap();
+        let used_addresses = |store: &StakingMemStore| match store.get(&addr1).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => val
+                .used_validator_addresses
+                .iter()
+                .map(|(val_addr, ts)| (val_addr.clone(), *ts))
+                .collect::<Vec<_>>(),
             _ => unreachable!(),
         };
+
+        // used at block time 1
+        let val_pk0 = validator_pubkey(&[0; 32]);
+        unbond_deposit_rejoin(&mut table, &mut store, addr1, amount, val_pk0.clone()).unwrap();
+        // used at block time 5
         let unbond = UnbondTx {
-            from_staked_account: addr,
-            nonce: staking.nonce,
+            from_staked_account: addr1,
+            nonce: store.get(&addr1).unwrap().nonce,
             value: amount,
             attributes: Default::default(),
         };
         table
-            .unbond(store, 10, 0, 1.into(), &unbond, Fee::zero())
+            .unbond(
+                &mut store,
+                &BlockContext::new(5, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
+            .unwrap();
+        table.end_block(&store, 3);
+        table.deposit(&mut store, 0, &addr1, amount).unwrap();
+        let node_join = NodeJoinRequestTx {
+            nonce: store.get(&addr1).unwrap().nonce,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[1; 32])),
+        };
+        table
+            .node_join(
+                &mut store,
+                &BlockContext::new(5, 0.into(), 10),
+                0,
+                0,
+                &node_join,
+            )
             .unwrap();
+        let val_addr1 = TendermintValidatorAddress::from(&validator_pubkey(&[0xcc; 32]));
+        let val_addr0 = TendermintValidatorAddress::from(&val_pk0);
         assert_eq!(
-            table.end_block(&*store, 3),
-            vec![(val_pk.clone(), Coin::zero().into())]
+            used_addresses(&store),
+            vec![(val_addr1.clone(), 1), (val_addr0.clone(), 5)]
         );
-        table.deposit(store, &addr, amount).unwrap();
-        let node_join = NodeJoinRequestTx {
-            nonce: staking.nonce + 1,
-            address: addr,
-            attributes: Default::default(),
-            node_meta: mock_council_node(val_pk_new.clone()),
+
+        // only pruned periodically by begin block
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 11,
+            block_height: (PRUNE_USED_ADDRESSES_INTERVAL - 1).into(),
+            voters: &[],
+            evidences: &[],
         };
-        // change to new validator key
-        let result = table.node_join(store, 1, 1, 0, &node_join);
-        if result.is_ok() {
-            let staking = store.get(&addr).unwrap();
-            assert_eq!(
-                table.end_block(&*store, 3),
-                vec![(val_pk_new, staking.bonded.into())]
-            );
-        }
-        result.map(|_| ())
+        table.begin_block(&mut store, &info);
+        assert_eq!(used_addresses(&store).len(), 2);
+
+        // nothing is out of date
+        table.prune_used_addresses(&mut store, 10, 10);
+        assert_eq!(used_addresses(&store).len(), 2);
+
+        // the stale one is removed, the recent one survives
+        table.prune_used_addresses(&mut store, 11, 10);
+        assert_eq!(used_addresses(&store), vec![(val_addr0.clone(), 5)]);
+        assert!(!table.idx_validator_address.contains_key(&val_addr1));
+        assert_eq!(table.idx_validator_address.get(&val_addr0), Some(&addr1));
+
+        // the desynced index entry is skipped
+        table.idx_validator_address.remove(&val_addr0);
+        table.prune_used_addresses(&mut store, 15, 10);
+        assert!(used_addresses(&store).is_empty());
+
+        let (mut table, mut store) = init_staking_table();
+        unbond_deposit_rejoin(&mut table, &mut store, addr1, amount, val_pk0).unwrap();
+        table.begin_block(
+            &mut store,
+            &BeginBlockInfo {
+                block_time: 11,
+                block_height: PRUNE_USED_ADDRESSES_INTERVAL.into(),
+                ..info
+            },
+        );
+        assert!(used_addresses(&store).is_empty());
+        assert!(!table.idx_validator_address.contains_key(&val_addr1));
     }
 
     #[test]
-    fn check_used_validator_key() {
-        let (mut table, mut store) = init_staking_table();
+    fn check_used_validator_addr_index_desync() {
+        let (table, mut store) = init_staking_table();
+        let mut table = table.with_max_used_validator_addr(2);
         let addr1 = staking_address(&[0xcc; 32]);
-        let val_pk1 = validator_pubkey(&[0xcc; 32]);
+        let amount = Coin::new(11_0000_0000).unwrap();
 
-        for i in 0..10 {
-            let val_pk_new = validator_pubkey(&[0x00 + i; 32]);
-            unbond_deposit_rejoin(
-                &mut table,
-                &mut store,
-                addr1,
-                Coin::new(11_0000_0000).unwrap(),
-                val_pk_new,
-            )
-            .unwrap();
+        for i in 0..2 {
+            let val_pk_new = validator_pubkey(&[i; 32]);
+            unbond_deposit_rejoin(&mut table, &mut store, addr1, amount, val_pk_new).unwrap();
         }
+        unbond_deposit_rejoin(
+            &mut table,
+            &mut store,
+            addr1,
+            amount,
+            validator_pubkey(&[2; 32]),
+        )
+        .unwrap_err();
 
-        // exceed max used validator address
-        let val_pk_new = validator_pubkey(&[0x00 + 10; 32]);
+        // corrupt the index of an out of date used address
+        let val_addr1 = TendermintValidatorAddress::from(&validator_pubkey(&[0xcc; 32]));
+        table.idx_validator_address.remove(&val_addr1);
+
+        let nonce = store.get(&addr1).unwrap().nonce;
+        let node_join = NodeJoinRequestTx {
+            nonce,
+            address: addr1,
+            attributes: Default::default(),
+            node_meta: mock_council_node(validator_pubkey(&[2; 32])),
+        };
         assert!(matches!(
-            unbond_deposit_rejoin(
-                &mut table,
+            table.node_join(
                 &mut store,
-                addr1,
-                Coin::new(11_0000_0000).unwrap(),
-                val_pk_new,
+                &BlockContext::new(1 + 1, 0.into(), 1),
+                0,
+                0,
+                &node_join
             ),
-            Err(PublicTxError::NodeJoin(
-                NodeJoinError::UsedValidatorAddrFull
-            ))
+            Err(PublicTxError::NodeJoin(NodeJoinError::IndexDesync))
         ));
+        // nothing changed
+        let val_addr = TendermintValidatorAddress::from(&validator_pubkey(&[0; 32]));
+        assert_eq!(table.idx_validator_address.get(&val_addr), Some(&addr1));
+        assert_eq!(store.get(&addr1).unwrap().nonce, nonce);
+    }
 
-        let addr_new = staking_address(&[0xcf; 32]);
+    #[test]
+    fn check_node_join_insert_index_desync() {
+        use parity_scale_codec::{Decode, Encode};
+
+        let (mut table, mut store) = init_staking_table();
+        let addr4 = staking_address(&[0xcf; 32]);
         table
-            .deposit(&mut store, &addr_new, Coin::new(10_0000_0000).unwrap())
+            .deposit(&mut store, 0, &addr4, Coin::new(10_0000_0000).unwrap())
             .unwrap();
+        let mut desynced_store = store.clone();
         let node_join = NodeJoinRequestTx {
             nonce: 0,
-            address: addr_new,
+            address: addr4,
             attributes: Default::default(),
-            node_meta: mock_council_node(val_pk1),
+            node_meta: mock_council_node(validator_pubkey(&[0xcf; 32])),
         };
-        // can't join with used key
+        let ctx = BlockContext::new(10, 0.into(), 0);
+        table.node_join(&mut store, &ctx, 0, 0, &node_join).unwrap();
+
+        // the table is ahead of the heap, the liveness tracker of addr4 is already there
+        let mut desynced = StakingTable::decode(&mut table.encode().as_slice()).unwrap();
+        desynced.minimal_required_staking = table.minimal_required_staking;
         assert!(matches!(
-            table.node_join(&mut store, 1, 0, 0, &node_join),
-            Err(PublicTxError::NodeJoin(
-                NodeJoinError::DuplicateValidatorAddress
-            ))
+            desynced.node_join(&mut desynced_store, &ctx, 0, 0, &node_join),
+            Err(PublicTxError::NodeJoin(NodeJoinError::IndexDesync))
         ));
+        // nothing changed
+        assert!(desynced.idx_validator_address.is_empty());
+        assert!(desynced.idx_keypackage.is_empty());
+        let staking = desynced_store.get(&addr4).unwrap();
+        assert_eq!(staking.nonce, 0);
+        assert_eq!(staking.node_meta, None);
     }
 
     #[test]
@@ -553,9 +4048,15 @@ mod tests {
 
         // re-join
         let slashed = (bonded_slashed + unbonded_slashed).unwrap();
-        table.deposit(&mut store, &addr1, slashed).unwrap();
+        table.deposit(&mut store, 0, &addr1, slashed).unwrap();
         table
-            .node_join(&mut store, 8, 0, 0, &node_join_tx(0))
+            .node_join(
+                &mut store,
+                &BlockContext::new(8, 0.into(), 0),
+                0,
+                0,
+                &node_join_tx(0),
+            )
             .unwrap();
         assert_eq!(
             table.end_block(&mut store, 3),
@@ -583,7 +4084,12 @@ mod tests {
             attributes: Default::default(),
         };
         table
-            .unbond(&mut store, 10, 10, 9.into(), &unbond, Fee::zero())
+            .unbond(
+                &mut store,
+                &BlockContext::new(10, 9.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
             .unwrap();
 
         assert_eq!(
@@ -604,10 +4110,16 @@ mod tests {
         }
 
         table
-            .deposit(&mut store, &addr1, Coin::new(11_0000_0000).unwrap())
+            .deposit(&mut store, 0, &addr1, Coin::new(11_0000_0000).unwrap())
             .unwrap();
         table
-            .node_join(&mut store, 11, 0, 0, &node_join_tx(2))
+            .node_join(
+                &mut store,
+                &BlockContext::new(11, 0.into(), 0),
+                0,
+                0,
+                &node_join_tx(2),
+            )
             .unwrap();
         assert_eq!(
             table.end_block(&mut store, 3),
@@ -686,7 +4198,12 @@ mod tests {
             attributes: Default::default(),
         };
         table
-            .unbond(&mut store, 10, 1, 1.into(), &unbond, Fee::zero())
+            .unbond(
+                &mut store,
+                &BlockContext::new(1, 1.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
             .unwrap();
         assert_eq!(store.get(&addr1).unwrap().unbonded, unbond_amount);
         let bonded = (bonded - unbond_amount).unwrap();
@@ -737,7 +4254,12 @@ mod tests {
             attributes: Default::default(),
         };
         table
-            .unbond(&mut store, 10, 2, 2.into(), &unbond, Fee::zero())
+            .unbond(
+                &mut store,
+                &BlockContext::new(2, 2.into(), 10),
+                &unbond,
+                Fee::zero(),
+            )
             .unwrap();
         assert_eq!(
             table.end_block(&mut store, 3),
@@ -746,7 +4268,7 @@ mod tests {
 
         // re-join with new pk
         table
-            .deposit(&mut store, &addr2, Coin::new(12_0000_0000).unwrap())
+            .deposit(&mut store, 0, &addr2, Coin::new(12_0000_0000).unwrap())
             .unwrap();
         let tx = NodeJoinRequestTx {
             nonce: 1,
@@ -754,7 +4276,9 @@ mod tests {
             attributes: Default::default(),
             node_meta: mock_council_node(val_pk_new.clone()),
         };
-        table.node_join(&mut store, 2, 0, 0, &tx).unwrap();
+        table
+            .node_join(&mut store, &BlockContext::new(2, 0.into(), 0), 0, 0, &tx)
+            .unwrap();
         assert_eq!(
             table.end_block(&mut store, 3),
             vec![(val_pk_new.clone(), Coin::new(12_0000_0000).unwrap().into())]
@@ -796,4 +4320,176 @@ mod tests {
         );
         assert!(staking.is_jailed());
     }
+
+    #[derive(Debug, PartialEq)]
+    enum Hooked {
+        Join(StakedStateAddress, Option<TendermintValidatorAddress>),
+        Unbond(StakedStateAddress, Coin),
+        Withdraw(StakedStateAddress, Coin),
+        Slash(StakedStateAddress, Coin),
+        Jail(StakedStateAddress, Timespec),
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingMetrics(std::sync::Arc<std::sync::Mutex<Vec<Hooked>>>);
+
+    impl RecordingMetrics {
+        fn record(&self, hooked: Hooked) {
+            self.0.lock().unwrap().push(hooked);
+        }
+
+        fn take(&self) -> Vec<Hooked> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl StakingMetrics for RecordingMetrics {
+        fn on_join(
+            &self,
+            addr: &StakedStateAddress,
+            validator_address: Option<&TendermintValidatorAddress>,
+        ) {
+            self.record(Hooked::Join(*addr, validator_address.cloned()));
+        }
+
+        fn on_unbond(&self, addr: &StakedStateAddress, amount: Coin) {
+            self.record(Hooked::Unbond(*addr, amount));
+        }
+
+        fn on_withdraw(&self, addr: &StakedStateAddress, amount: Coin) {
+            self.record(Hooked::Withdraw(*addr, amount));
+        }
+
+        fn on_slash(&self, addr: &StakedStateAddress, amount: Coin) {
+            self.record(Hooked::Slash(*addr, amount));
+        }
+
+        fn on_jail(&self, addr: &StakedStateAddress, jailed_until: Timespec) {
+            self.record(Hooked::Jail(*addr, jailed_until));
+        }
+    }
+
+    #[test]
+    fn check_staking_metrics() {
+        let (table, mut store) = init_staking_table();
+        let metrics = RecordingMetrics::default();
+        let mut table = table.with_metrics(std::sync::Arc::new(metrics.clone()));
+        let addr1 = staking_address(&[0xcc; 32]);
+        let addr2 = staking_address(&[0xcd; 32]);
+        let amount = Coin::new(11_0000_0000).unwrap();
+        let ctx = BlockContext::new(0, 1.into(), 10);
+        let unbond = UnbondTx {
+            from_staked_account: addr1,
+            nonce: 0,
+            value: amount,
+            attributes: Default::default(),
+        };
+
+        // dry runs and failed operations don't report
+        table
+            .validate_unbond(&store, &ctx, &unbond, Fee::zero())
+            .unwrap();
+        let wrong_nonce = UnbondTx {
+            nonce: 1,
+            ..unbond.clone()
+        };
+        assert!(table
+            .unbond(&mut store, &ctx, &wrong_nonce, Fee::zero())
+            .is_err());
+        assert!(metrics.take().is_empty());
+
+        table
+            .unbond(&mut store, &ctx, &unbond, Fee::zero())
+            .unwrap();
+        table
+            .withdraw(
+                &mut store,
+                &BlockContext::new(10, 1.into(), 10),
+                &addr1,
+                amount,
+            )
+            .unwrap();
+        table.deposit(&mut store, 0, &addr1, amount).unwrap();
+        table.reactivate(&mut store, &addr1, 2).unwrap();
+        assert_eq!(
+            metrics.take(),
+            vec![
+                Hooked::Unbond(addr1, amount),
+                Hooked::Withdraw(addr1, amount),
+                Hooked::Join(
+                    addr1,
+                    Some(TendermintValidatorAddress::from(&validator_pubkey(
+                        &[0xcc; 32]
+                    )))
+                ),
+            ]
+        );
+
+        let params = NetworkParameters::Genesis(get_init_network_params(Coin::zero()));
+        let info = BeginBlockInfo {
+            params: &params,
+            max_evidence_age: 10,
+            block_time: 1,
+            block_height: 1.into(),
+            voters: &[],
+            evidences: &[],
+        };
+        let slashed = table
+            .slash(
+                &mut store,
+                &info,
+                &addr2,
+                "0.1".parse().unwrap(),
+                PunishmentKind::ByzantineFault,
+            )
+            .unwrap();
+        let jailed_until = match store.get(&addr2).unwrap().node_meta {
+            Some(NodeState::CouncilNode(val)) => val.jailed_until.unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            metrics.take(),
+            vec![
+                Hooked::Jail(addr2, jailed_until),
+                Hooked::Slash(addr2, slashed)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_staking_metrics_reinstall() {
+        use parity_scale_codec::{Decode, Encode};
+
+        let (table, mut store) = init_staking_table();
+        let metrics = RecordingMetrics::default();
+        let table = table.with_metrics(std::sync::Arc::new(metrics.clone()));
+        let addr1 = staking_address(&[0xcc; 32]);
+        let amount = Coin::new(1_0000_0000).unwrap();
+        let ctx = BlockContext::new(0, 1.into(), 10);
+        let unbond = |nonce| UnbondTx {
+            from_staked_account: addr1,
+            nonce,
+            value: amount,
+            attributes: Default::default(),
+        };
+
+        // the clones (e.g. mempool state) and the restored table don't report
+        let mut cloned = table.clone();
+        cloned
+            .unbond(&mut store, &ctx, &unbond(0), Fee::zero())
+            .unwrap();
+        let mut restored = StakingTable::decode(&mut table.encode().as_slice()).unwrap();
+        restored.initialize(&store, Coin::new(10_0000_0000).unwrap(), 50);
+        restored
+            .unbond(&mut store, &ctx, &unbond(1), Fee::zero())
+            .unwrap();
+        assert!(metrics.take().is_empty());
+
+        // until installed again, like `ChainNodeApp::with_staking_metrics` does
+        restored.set_metrics(std::sync::Arc::new(metrics.clone()));
+        restored
+            .unbond(&mut store, &ctx, &unbond(2), Fee::zero())
+            .unwrap();
+        assert_eq!(metrics.take(), vec![Hooked::Unbond(addr1, amount)]);
+    }
 }
@@ -2,29 +2,72 @@
 //! so many methods here needs caller to provide access to external merkle trie
 //! through traits `GetStaking` and `StoreStaking`.
 
+use std::collections::btree_map::Entry;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::iter;
+use std::sync::Arc;
 
-use core::cmp::Ordering;
+use core::cmp::{self, Ordering};
 use parity_scale_codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
-use chain_core::common::Timespec;
+use chain_core::common::{Timespec, H256};
 use chain_core::init::coin::{sum_coins, Coin, CoinError, CoinResult};
 use chain_core::init::config::SlashRatio;
+use chain_core::init::MAX_COIN_DECIMALS;
 use chain_core::state::account::{
-    NodeName, NodeSecurityContact, NodeState, PunishmentKind, SlashRecord, StakedState,
-    StakedStateAddress,
+    CouncilNodeMeta, JailReason, NodeName, NodeSecurityContact, NodeState, PunishmentKind,
+    SlashRecord, StakedState, StakedStateAddress, Validator,
 };
 use chain_core::state::tendermint::{
     BlockHeight, TendermintValidatorAddress, TendermintValidatorPubKey, TendermintVotePower,
+    TENDERMINT_MAX_VOTE_POWER,
 };
-use chain_storage::buffer::{GetStaking, StoreStaking};
+use chain_core::tx::data::txid_hash;
+use chain_storage::buffer::{GetStaking, IterStaking, MemStore, StoreStaking};
 
+use super::config::StakingConfig;
+use super::metrics::{MetricsHook, StakingMetrics};
 use crate::app::BeginBlockInfo;
 use crate::liveness::LivenessTracker;
 
 pub type RewardsDistribution = Vec<(StakedStateAddress, Coin)>;
 
+/// Default max number of used validator addresses kept in a validator record
+pub const DEFAULT_MAX_USED_VALIDATOR_ADDR: usize = 10;
+
+/// Default penalty divisor of immediate unbond, i.e. 10% of the unbonded amount is burned
+pub const DEFAULT_IMMEDIATE_UNBOND_PENALTY_DIVISOR: u64 = 10;
+
+/// Default max number of active validators admitted by node join, i.e. no limit, until it's set to
+/// the network parameter by `from_genesis` or `initialize`
+pub const DEFAULT_MAX_VALIDATORS: usize = usize::MAX;
+
+/// Interval in blocks of pruning the out of date used validator addresses in `begin_block`
+pub const PRUNE_USED_ADDRESSES_INTERVAL: u64 = 100;
+
+/// Default amount of base units per voting power unit, i.e. one voting power per whole coin
+pub const DEFAULT_VOTING_POWER_UNIT: u64 = MAX_COIN_DECIMALS;
+
+/// Where the fee of unbond tx goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum FeeDestination {
+    /// Deducted from the bonded coins only, the fee leaves the staking table,
+    /// chain-abci adds it to the rewards pool
+    Burn,
+    /// Credited to the bonded coins of the treasury account
+    Treasury(StakedStateAddress),
+}
+
+impl Default for FeeDestination {
+    fn default() -> Self {
+        FeeDestination::Burn
+    }
+}
+
+/// Version byte prefixed to the exported snapshot of staking table
+pub const SNAPSHOT_VERSION: u8 = 1;
+
 #[derive(Debug, Clone, Serialize)]
 /// Metadata of a validator
 pub struct CouncilNodeMetadata {
@@ -105,7 +148,9 @@ impl SlashedCoin {
 /// Invariant 2.1: The secondary indexes should always be consistent with the heap
 ///
 /// Invariant 2.2:
-///   All the secondary indexes should be partial index with condition `validator is not null`
+///   All the secondary indexes should be partial index with condition `validator is not null`,
+///   the permanently jailed validators are removed from the indexes by cleanup, but the validator
+///   records are kept on heap.
 ///   This shouldn't be prone to DoS as long as minimum required stake is high enough.
 ///   Combined with 2.1, it means that all addresses recorded in `idx_*` should also exist on heap,
 ///   and have validator record;
@@ -120,7 +165,7 @@ impl SlashedCoin {
 /// Invariant 2.4:
 ///   idx_* only contains CouncilNode not CommunityNode
 ///   Proof: checked during insertion
-#[derive(Clone, Debug, Default, Encode, Decode)]
+#[derive(Clone, Debug, Encode, Decode)]
 pub struct StakingTable {
     // Selected validator voting powers of last executed end block
     chosen_validators: BTreeMap<StakedStateAddress, TendermintVotePower>,
@@ -135,6 +180,77 @@ pub struct StakingTable {
     pub(crate) idx_validator_address: BTreeMap<TendermintValidatorAddress, StakedStateAddress>,
     #[codec(skip)]
     idx_sort: BTreeSet<ValidatorSortKey>,
+    // Hashes of the current keypackages of validators, to reject keypackages already in the mls tree.
+    #[codec(skip)]
+    pub(crate) idx_keypackage: BTreeMap<H256, StakedStateAddress>,
+    // Policies set by `configure` or the `with_*` methods, not part of the state,
+    // reset to default in `initialize`.
+    #[codec(skip)]
+    pub(crate) config: StakingConfig,
+    // Max number of active validators admitted by node join, same as the one used by `end_block`,
+    // not part of the state, set in `initialize`.
+    #[codec(skip)]
+    pub(crate) max_validators: usize,
+    // Hooks of the successful operations, not part of the state, reset to default in `initialize`.
+    #[codec(skip)]
+    pub(crate) metrics: MetricsHook,
+}
+
+impl Default for StakingTable {
+    fn default() -> Self {
+        Self {
+            chosen_validators: BTreeMap::new(),
+            liveness: BTreeMap::new(),
+            participator_stats: BTreeMap::new(),
+            minimal_required_staking: Coin::zero(),
+            idx_validator_address: BTreeMap::new(),
+            idx_sort: BTreeSet::new(),
+            idx_keypackage: BTreeMap::new(),
+            config: StakingConfig::default(),
+            max_validators: DEFAULT_MAX_VALIDATORS,
+            metrics: MetricsHook::default(),
+        }
+    }
+}
+
+/// Broken invariant found by `StakingTable::verify_invariants`
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// validator address is indexed to a staking which doesn't use it
+    #[error("validator address {0} is not used by staking {1}")]
+    DuplicateValidatorAddress(TendermintValidatorAddress, StakedStateAddress),
+    /// staking address appears multiple times in the sorted index
+    #[error("duplicate staking address in sorted index")]
+    DuplicateSortKey,
+    /// bonded amount recorded in the sorted index doesn't match the heap
+    #[error("bonded amount of {0} in index doesn't match the heap")]
+    VotingPowerMismatch(StakedStateAddress),
+    /// bonded + unbonded is out of bound
+    #[error("invalid balance of {0}")]
+    InvalidBalance(StakedStateAddress),
+    /// internal indexes are inconsistent with the heap
+    #[error("index of {0} is inconsistent with the heap")]
+    IndexDesync(StakedStateAddress),
+    /// validator record is invalid
+    #[error("invalid validator record of {0}")]
+    InvalidValidator(StakedStateAddress),
+}
+
+/// Error of `StakingTable::import_snapshot`
+#[derive(thiserror::Error, Debug)]
+pub enum SnapshotError {
+    /// the snapshot is empty
+    #[error("empty snapshot")]
+    Empty,
+    /// the snapshot is exported by an unknown version
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+    /// the snapshot can't be decoded
+    #[error("failed to decode snapshot: {0}")]
+    Decode(#[from] parity_scale_codec::Error),
+    /// the table and the stakings in the snapshot are inconsistent
+    #[error("invalid snapshot: {0}")]
+    Invalid(#[from] InvariantViolation),
 }
 
 /// Returned if the caller did not do the necessary validations
@@ -148,7 +264,9 @@ pub enum StakingTableInsertionError {
 }
 
 impl StakingTable {
-    /// Init with genesis stakings
+    /// Init with genesis stakings, `max_validators` is the network parameter, which limits both the
+    /// chosen validators and the node joins, when the validator set is full, a new validator is
+    /// only admitted if it outbids the lowest-staked one in the set.
     ///
     /// # Panics
     ///
@@ -162,6 +280,7 @@ impl StakingTable {
     ) -> Self {
         let mut tbl = Self::default();
         tbl.minimal_required_staking = minimal_required_staking;
+        tbl.max_validators = max_validators;
         for addr in addresses.iter() {
             tbl.insert_validator(&heap.get(addr).unwrap())
                 .expect("only validator");
@@ -172,27 +291,289 @@ impl StakingTable {
         tbl
     }
 
-    /// After restored from storage, call initialize to populate the indexes
-    pub fn initialize(&mut self, heap: &impl GetStaking, minimal_required_staking: Coin) {
+    /// Set the max number of used validator addresses kept in a validator record,
+    /// default to `DEFAULT_MAX_USED_VALIDATOR_ADDR`.
+    pub fn with_max_used_validator_addr(mut self, max_used_validator_addr: usize) -> Self {
+        self.config.max_used_validator_addr = max_used_validator_addr;
+        self
+    }
+
+    /// Set the amount of base units per voting power unit, default to `DEFAULT_VOTING_POWER_UNIT`.
+    /// Testnets can use a smaller unit.
+    ///
+    /// # Panics
+    ///
+    /// Panic if `voting_power_unit` is zero.
+    pub fn with_voting_power_unit(mut self, voting_power_unit: u64) -> Self {
+        assert!(voting_power_unit > 0, "voting power unit can't be zero");
+        self.config.voting_power_unit = voting_power_unit;
+        self
+    }
+
+    /// Set the penalty divisor of immediate unbond,
+    /// default to `DEFAULT_IMMEDIATE_UNBOND_PENALTY_DIVISOR`.
+    ///
+    /// # Panics
+    ///
+    /// Panic if `divisor` is zero.
+    pub fn with_immediate_unbond_penalty_divisor(mut self, divisor: u64) -> Self {
+        assert!(divisor > 0, "penalty divisor can't be zero");
+        self.config.immediate_unbond_penalty_divisor = divisor;
+        self
+    }
+
+    /// Enable the escalating jail duration, a validator jailed again within `grace_period` after
+    /// the last jail expired is jailed `consecutive count` times as long, disabled by default.
+    pub fn with_jail_escalation(mut self, grace_period: Timespec) -> Self {
+        self.config.jail_escalation_grace_period = Some(grace_period);
+        self
+    }
+
+    /// Accept a retried `UnjailTx` as no-op, if the validator is not jailed, the tx nonce is exactly
+    /// one behind, and the last unjail was within `window`, disabled by default.
+    pub fn with_idempotent_unjail(mut self, window: Timespec) -> Self {
+        self.config.idempotent_unjail_window = Some(window);
+        self
+    }
+
+    /// Reject `NodeJoinTx` from enclaves with an isv_svn older than the most recent one, instead of
+    /// accepting it and keeping the most recent one, disabled by default.
+    pub fn with_reject_svn_downgrade(mut self, reject: bool) -> Self {
+        self.config.reject_svn_downgrade = reject;
+        self
+    }
+
+    /// Set a separate minimal bonded coins for community node join, council nodes still require
+    /// `minimal_required_staking`, both are the same by default.
+    pub fn with_community_node_minimal_staking(mut self, minimal: Coin) -> Self {
+        self.config.community_node_minimal_staking = Some(minimal);
+        self
+    }
+
+    /// Set where the fee of unbond tx goes, default to `FeeDestination::Burn`.
+    /// The fee credited to the treasury is still reported as the tx fee in `UnbondResult`,
+    /// so it must not be added to the rewards pool again.
+    pub fn with_fee_destination(mut self, fee_destination: FeeDestination) -> Self {
+        self.config.fee_destination = fee_destination;
+        self
+    }
+
+    /// Set the minimal value of unbond tx, smaller ones are rejected, default to zero.
+    pub fn with_min_unbond_amount(mut self, min_unbond_amount: Coin) -> Self {
+        self.config.min_unbond_amount = min_unbond_amount;
+        self
+    }
+
+    /// Jail the byzantine faults permanently, instead of for the unbonding period like the
+    /// liveness faults, disabled by default.
+    /// The permanently jailed stakings can still unbond and withdraw the coins left after the
+    /// slash, and the validator records are kept after cleanup, so they can't re-join.
+    pub fn with_permanent_jail(mut self, permanent_jail: bool) -> Self {
+        self.config.permanent_jail = permanent_jail;
+        self
+    }
+
+    /// Set the hooks invoked at the end of the successful operations, none by default.
+    /// The copies of the table don't report, see `MetricsHook`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn StakingMetrics>) -> Self {
+        self.set_metrics(metrics);
+        self
+    }
+
+    /// Same as `with_metrics`, for the table held in the app state.
+    /// The hook is dropped by `clone`, `decode` and `initialize`, install it again afterwards.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn StakingMetrics>) {
+        self.metrics = MetricsHook::new(metrics);
+    }
+
+    /// After restored from storage, call initialize to populate the indexes, `max_validators` is the
+    /// network parameter like in `from_genesis`.
+    /// The policies are reset to default, call `configure` afterwards to apply the config.
+    /// Only the validator records of the indexed addresses are read, if they are inconsistent with
+    /// the table, an error is logged and the indexes are rebuilt with `rebuild_index`.
+    /// Complexity: O(N), N is the number of validators, O(M) if rebuilt, M is the number of all the
+    /// stakings.
+    pub fn initialize(
+        &mut self,
+        heap: &impl IterStaking,
+        minimal_required_staking: Coin,
+        max_validators: usize,
+    ) {
         assert!(self.idx_sort.is_empty());
         assert!(self.idx_validator_address.is_empty());
         self.minimal_required_staking = minimal_required_staking;
+        self.config = StakingConfig::default();
+        self.max_validators = max_validators;
+        self.metrics = MetricsHook::default();
+
+        if let Err(err) = self.populate_index(heap) {
+            log::error!(
+                "staking table is inconsistent with the heap, rebuild the indexes: {}",
+                err
+            );
+            *self = self.rebuild_index(heap);
+        }
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+    }
 
+    /// Populate the indexes from the validator records of the addresses in `liveness`,
+    /// stops at the first record inconsistent with the table, the indexes are partially populated
+    /// then.
+    fn populate_index(&mut self, heap: &impl GetStaking) -> Result<(), InvariantViolation> {
         for (addr, _) in self.liveness.iter() {
-            // no panic: Invariant 2.3 + 2.2 + 2.1
-            // liveness and heap and idx_* are always consistent
-            let mut staking = heap.get(addr).unwrap();
-            assert!(self.idx_sort.insert((&staking).into()));
-            if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
-                assert!(self
-                    .idx_validator_address
-                    .insert(val.validator_address(), *addr)
-                    .is_none());
-            } else {
-                // no panic: Invariant 2.4
-                unreachable!("only council node addresses stored in internal indicies");
+            // Invariant 2.3 + 2.2 + 2.1: liveness and heap and idx_* are consistent
+            let staking = heap
+                .get(addr)
+                .ok_or(InvariantViolation::IndexDesync(*addr))?;
+            // Invariant 2.4: only council node addresses stored in internal indicies
+            let val = match &staking.node_meta {
+                Some(NodeState::CouncilNode(val)) => val,
+                _ => return Err(InvariantViolation::IndexDesync(*addr)),
+            };
+            if !self.idx_sort.insert((&staking).into()) {
+                return Err(InvariantViolation::DuplicateSortKey);
+            }
+            let val_addrs = iter::once(val.validator_address()).chain(
+                val.used_validator_addresses
+                    .iter()
+                    .map(|(val_addr, _)| val_addr.clone()),
+            );
+            for val_addr in val_addrs {
+                // the used addresses are not used by other validators
+                match self.idx_validator_address.insert(val_addr.clone(), *addr) {
+                    Some(owner) if owner != *addr => {
+                        return Err(InvariantViolation::DuplicateValidatorAddress(
+                            val_addr, owner,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            self.idx_keypackage
+                .insert(keypackage_hash(&val.council_node), *addr);
+        }
+        Ok(())
+    }
+
+    /// Rebuild the indexes (including the used validator addresses) by scanning all the stakings
+    /// on heap, to recover from indexes out of sync with the heap, `initialize` falls back to it.
+    /// The validator records on heap are the source of truth, the liveness trackers of addresses
+    /// without validator record are dropped, and the missing ones are started fresh.
+    /// If a validator address or keypackage is used by multiple stakings, the first one in the heap
+    /// order is kept and an error is logged.
+    /// The metrics hook is not kept in the rebuilt table.
+    /// Complexity: O(N), N is the number of all the stakings.
+    pub fn rebuild_index(&self, heap: &impl IterStaking) -> Self {
+        let mut tbl = Self {
+            liveness: BTreeMap::new(),
+            idx_validator_address: BTreeMap::new(),
+            idx_sort: BTreeSet::new(),
+            idx_keypackage: BTreeMap::new(),
+            ..self.clone()
+        };
+        for staking in heap.iter_stakings() {
+            // Invariant 2.4: only council nodes are indexed
+            if let Some(NodeState::CouncilNode(val)) = &staking.node_meta {
+                // Invariant 2.2: the permanently jailed ones are not indexed after cleanup
+                if val.is_permanently_jailed() && !self.liveness.contains_key(&staking.address) {
+                    continue;
+                }
+                let val_addrs = iter::once(val.validator_address()).chain(
+                    val.used_validator_addresses
+                        .iter()
+                        .map(|(val_addr, _)| val_addr.clone()),
+                );
+                for val_addr in val_addrs {
+                    match tbl.idx_validator_address.entry(val_addr) {
+                        Entry::Vacant(entry) => {
+                            entry.insert(staking.address);
+                        }
+                        Entry::Occupied(entry) if *entry.get() != staking.address => {
+                            log::error!(
+                                "validator address {} of {} is already used by {}",
+                                entry.key(),
+                                staking.address,
+                                entry.get()
+                            )
+                        }
+                        Entry::Occupied(_) => {}
+                    }
+                }
+                tbl.idx_sort.insert((&staking).into());
+                match tbl.idx_keypackage.entry(keypackage_hash(&val.council_node)) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(staking.address);
+                    }
+                    Entry::Occupied(entry) => log::error!(
+                        "keypackage of {} is already used by {}",
+                        staking.address,
+                        entry.get()
+                    ),
+                }
+                let tracker = self
+                    .liveness
+                    .get(&staking.address)
+                    .cloned()
+                    .unwrap_or_default();
+                tbl.liveness.insert(staking.address, tracker);
             }
         }
+        if tbl.liveness.keys().ne(self.liveness.keys()) {
+            log::warn!("liveness trackers are out of sync with the validator records, rebuilt");
+        }
+        let liveness = &tbl.liveness;
+        tbl.participator_stats
+            .retain(|addr, _| liveness.contains_key(addr));
+        tbl
+    }
+
+    /// Export the table and the validator stakings referenced by it into a portable blob:
+    /// `SNAPSHOT_VERSION` byte followed by the SCALE encoded
+    /// `(StakingTable, minimal_required_staking, Vec<StakedState>)`.
+    /// The indexes and configs are not exported, they are restored by `import_snapshot`.
+    pub fn export_snapshot(&self, heap: &impl GetStaking) -> Vec<u8> {
+        let stakings = self
+            .liveness
+            .keys()
+            // no panic: Invariant 2.3 + 2.2
+            .map(|addr| heap.get(addr).unwrap())
+            .collect::<Vec<_>>();
+        let mut bytes = vec![SNAPSHOT_VERSION];
+        (self, self.minimal_required_staking, stakings).encode_to(&mut bytes);
+        bytes
+    }
+
+    /// Restore the table exported by `export_snapshot` and write the stakings into `heap`,
+    /// the configs are default like after `initialize`.
+    /// Nothing is written if the snapshot is invalid.
+    pub fn import_snapshot(
+        bytes: &[u8],
+        heap: &mut impl StoreStaking,
+    ) -> Result<Self, SnapshotError> {
+        let (version, mut payload) = bytes.split_first().ok_or(SnapshotError::Empty)?;
+        if *version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(*version));
+        }
+        let (decoded, minimal_required_staking, stakings) =
+            <(StakingTable, Coin, Vec<StakedState>)>::decode(&mut payload)?;
+        let mut store = MemStore::<StakedStateAddress, StakedState>::new();
+        for staking in stakings.into_iter() {
+            store.set_staking(staking);
+        }
+        let table = Self {
+            chosen_validators: decoded.chosen_validators,
+            liveness: decoded.liveness,
+            participator_stats: decoded.participator_stats,
+            minimal_required_staking,
+            ..Self::default()
+        }
+        .rebuild_index(&store);
+        table.verify_invariants(&store)?;
+        for staking in store.iter_stakings() {
+            heap.set_staking(staking);
+        }
+        Ok(table)
     }
 
     /// Handle abci begin_block event
@@ -207,12 +588,19 @@ impl StakingTable {
         info: &BeginBlockInfo,
     ) -> Vec<PunishmentOutcome> {
         self.cleanup(heap, info.get_unbonding_period(), info.block_time);
+        // full scan, so only periodically, the out of date addresses are kept a bit longer meanwhile
+        if info.block_height.value() % PRUNE_USED_ADDRESSES_INTERVAL == 0 {
+            self.prune_used_addresses(heap, info.block_time, info.max_evidence_age);
+        }
         self.punish(heap, info)
     }
 
     /// Handle abci end_block event
     /// - Compute validator updates
     /// - Cleanup out dated validator records
+    ///
+    /// `max_validators` should be the network parameter passed to `from_genesis` or `initialize`,
+    /// which also limits the node joins.
     pub fn end_block(
         &mut self,
         heap: &impl GetStaking,
@@ -304,7 +692,7 @@ impl StakingTable {
                     if val.is_active() {
                         Some(CouncilNodeMetadata {
                             name: val.council_node.node_info.name.clone(),
-                            voting_power: staking.bonded.into(),
+                            voting_power: self.voting_power(&staking),
                             staking_address: key.address,
                             security_contact: val.council_node.node_info.security_contact.clone(),
                             tendermint_pubkey: val.council_node.consensus_pubkey.clone(),
@@ -333,6 +721,172 @@ impl StakingTable {
         &self.chosen_validators
     }
 
+    /// List the active, non-jailed validators bonded at least minimal required staking,
+    /// order by voting power desc, validator address.
+    pub fn active_validators(
+        &self,
+        heap: &impl GetStaking,
+    ) -> Vec<(TendermintValidatorAddress, TendermintVotePower)> {
+        let mut validators = self
+            .idx_sort
+            .iter()
+            .filter_map(|key| {
+                // no panic: Invariant 2.1
+                let staking = heap.get(&key.address).unwrap();
+                let power = self.voting_power(&staking);
+                match staking.node_meta {
+                    Some(NodeState::CouncilNode(val)) if power > TendermintVotePower::zero() => {
+                        Some((val.validator_address(), power))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        validators.sort_by(|(addr1, power1), (addr2, power2)| {
+            power2.cmp(power1).then_with(|| addr1.cmp(addr2))
+        });
+        validators
+    }
+
+    /// Iterate all the stored stakings order by address, for state export.
+    /// All the addresses are kept in memory to sort them, see `IterStaking::iter_stakings`.
+    pub fn iter_accounts<'a>(
+        &'a self,
+        heap: &'a impl IterStaking,
+    ) -> impl Iterator<Item = (StakedStateAddress, StakedState)> + 'a {
+        heap.iter_stakings()
+            .map(|staking| (staking.address, staking))
+    }
+
+    /// The minimal bonded coins needed for a new validator to join the active set right now:
+    /// the minimal required staking if the validator set is not full, otherwise it needs to
+    /// outbid the lowest-staked validator by at least one base unit.
+    pub fn min_bond_to_join(&self, heap: &impl GetStaking) -> Coin {
+        match self.lowest_in_full_set(heap) {
+            Some(lowest) => {
+                // no one can outbid if it's already the max coin
+                let outbid = (lowest.bonded + Coin::unit()).unwrap_or_else(|_| Coin::max());
+                cmp::max(outbid, self.minimal_required_staking)
+            }
+            None => self.minimal_required_staking,
+        }
+    }
+
+    /// Sum the `(bonded, unbonded)` coins of all the stored stakings.
+    /// Complexity: O(N), it's recomputed from the heap on every call rather than cached,
+    /// because the balances are changed in many places (slashing, rewards, unbond, withdraw),
+    /// and the cache would need to be rebuilt by a full scan after restart anyway.
+    pub fn total_staked(&self, heap: &impl IterStaking) -> Result<(Coin, Coin), CoinError> {
+        let mut bonded = Coin::zero();
+        let mut unbonded = Coin::zero();
+        for staking in heap.iter_stakings() {
+            bonded = (bonded + staking.bonded)?;
+            unbonded = (unbonded + staking.total_unbonded()?)?;
+        }
+        Ok((bonded, unbonded))
+    }
+
+    /// Compute the tendermint voting power of the staking,
+    /// zero if it's not an active validator, jailed, or bonded less than minimal required staking.
+    pub fn voting_power(&self, staking: &StakedState) -> TendermintVotePower {
+        match &staking.node_meta {
+            Some(NodeState::CouncilNode(val))
+                if val.is_active()
+                    && !val.is_jailed()
+                    && staking.bonded >= self.minimal_required_staking =>
+            {
+                let power = u64::from(staking.bonded) / self.config.voting_power_unit;
+                // no panic: the value is non-negative and bounded
+                TendermintVotePower::new((power as i64).min(TENDERMINT_MAX_VOTE_POWER))
+                    .expect("voting power in bound")
+            }
+            _ => TendermintVotePower::zero(),
+        }
+    }
+
+    /// Query the staking state, `None` if not exists, the heap is not modified.
+    pub fn get_account(
+        &self,
+        heap: &impl GetStaking,
+        addr: &StakedStateAddress,
+    ) -> Option<StakedState> {
+        heap.get(addr)
+    }
+
+    /// Query the time until which the validator is jailed, `None` if not jailed or not a validator.
+    pub fn jailed_until(
+        &self,
+        heap: &impl GetStaking,
+        addr: &StakedStateAddress,
+    ) -> Option<Timespec> {
+        match heap.get(addr)?.node_meta {
+            Some(NodeState::CouncilNode(val)) => val.jailed_until,
+            _ => None,
+        }
+    }
+
+    /// Query the jailed validators which can be unjailed at `block_time`, i.e. `jailed_until <=
+    /// block_time`, ordered by staking address, permanently jailed ones are excluded.
+    /// Complexity: O(N), like the other per-block scans of validators, a time-ordered index would
+    /// need to be kept in sync by every jail/unjail/cleanup path and restored in `initialize`.
+    pub fn validators_unjailable_at(
+        &self,
+        heap: &impl GetStaking,
+        block_time: Timespec,
+    ) -> Vec<StakedStateAddress> {
+        self.liveness
+            .keys()
+            .filter(|addr| {
+                // no panic: Invariant 2.3 + 2.2
+                match heap.get(addr).unwrap().node_meta {
+                    Some(NodeState::CouncilNode(val)) => {
+                        !val.is_permanently_jailed()
+                            && val.jailed_until.map_or(false, |until| until <= block_time)
+                    }
+                    _ => false,
+                }
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Query the jailed validators with their `jailed_until`, ordered by staking address,
+    /// including the permanently jailed ones until cleaned up, for monitoring the health of the
+    /// validator set.
+    /// Complexity: O(N), N is the number of validators.
+    pub fn jailed_validators(&self, heap: &impl GetStaking) -> Vec<(StakedStateAddress, Timespec)> {
+        self.liveness
+            .keys()
+            .filter_map(|addr| {
+                // no panic: Invariant 2.3 + 2.2
+                match heap.get(addr).unwrap().node_meta {
+                    Some(NodeState::CouncilNode(val)) => {
+                        val.jailed_until.map(|until| (*addr, until))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Query the unbonding schedule of the staking, the `(amount, unbonded_from)` batches sorted by
+    /// time, the first one could be withdrawable already, empty if nothing is unbonded.
+    pub fn unbonding_schedule(
+        &self,
+        heap: &impl GetStaking,
+        addr: &StakedStateAddress,
+    ) -> Vec<(Coin, Timespec)> {
+        match heap.get(addr) {
+            Some(staking) if staking.unbonded > Coin::zero() => {
+                let mut schedule = Vec::with_capacity(staking.unbonding_queue.len() + 1);
+                schedule.push((staking.unbonded, staking.unbonded_from));
+                schedule.extend(staking.unbonding_queue);
+                schedule
+            }
+            _ => Vec::new(),
+        }
+    }
+
     /// Insert validator (genesis or join-node tx)
     /// Caller should do the validations:
     /// - StakedState has validator record
@@ -344,28 +898,26 @@ impl StakingTable {
         &mut self,
         staking: &StakedState,
     ) -> Result<(), StakingTableInsertionError> {
-        let val_addr = TendermintValidatorAddress::from(match &staking.node_meta {
-            Some(NodeState::CouncilNode(v)) => Ok(v.council_node.consensus_pubkey.clone()),
+        let council_node = match &staking.node_meta {
+            Some(NodeState::CouncilNode(v)) => Ok(&v.council_node),
             _ => Err(StakingTableInsertionError::NoCouncilNode),
-        }?);
-        // insert
-        if self
-            .idx_validator_address
-            .insert(val_addr, staking.address)
-            .is_some()
+        }?;
+        let val_addr = TendermintValidatorAddress::from(&council_node.consensus_pubkey);
+        let key: ValidatorSortKey = staking.into();
+        // check before inserting anything, so the indexes are untouched on error
+        if self.idx_validator_address.contains_key(&val_addr)
+            || self.idx_sort.contains(&key)
+            || self.liveness.contains_key(&staking.address)
         {
             return Err(StakingTableInsertionError::AlreadyInsertedInIndex);
         }
-        if !self.idx_sort.insert(staking.into()) {
-            return Err(StakingTableInsertionError::AlreadyInsertedInIndex);
-        }
-
-        let tracker = LivenessTracker::new();
-        if self.liveness.insert(staking.address, tracker).is_none() {
-            Ok(())
-        } else {
-            Err(StakingTableInsertionError::AlreadyInsertedInIndex)
-        }
+        self.idx_validator_address.insert(val_addr, staking.address);
+        self.idx_sort.insert(key);
+        self.idx_keypackage
+            .insert(keypackage_hash(council_node), staking.address);
+        self.liveness
+            .insert(staking.address, LivenessTracker::new());
+        Ok(())
     }
 
     /// Change bonded, and related index, inactivate validator if not enough amount.
@@ -377,11 +929,13 @@ impl StakingTable {
         staking: &mut StakedState,
     ) -> Result<(), CoinError> {
         let bonded = (staking.bonded - amount)?;
-        if staking.has_council_node_meta() {
+        // Invariant 2.3: the permanently jailed validators are not indexed after cleanup
+        let indexed = self.liveness.contains_key(&staking.address);
+        if indexed {
             assert!(self.idx_sort.remove(&staking.into()));
         }
         staking.bonded = bonded;
-        if staking.has_council_node_meta() {
+        if indexed {
             assert!(self.idx_sort.insert(staking.into()));
         }
 
@@ -400,11 +954,13 @@ impl StakingTable {
         staking: &mut StakedState,
     ) -> Result<(), CoinError> {
         let bonded = (staking.bonded + amount)?;
-        if staking.has_council_node_meta() {
+        // Invariant 2.3: the permanently jailed validators are not indexed after cleanup
+        let indexed = self.liveness.contains_key(&staking.address);
+        if indexed {
             assert!(self.idx_sort.remove(&staking.into()));
         }
         staking.bonded = bonded;
-        if staking.has_council_node_meta() {
+        if indexed {
             assert!(self.idx_sort.insert(staking.into()));
         }
 
@@ -412,7 +968,7 @@ impl StakingTable {
     }
 
     /// execute slash
-    fn slash(
+    fn execute_slash(
         &mut self,
         block_time: Timespec,
         block_height: BlockHeight,
@@ -420,12 +976,11 @@ impl StakingTable {
         ratio: SlashRatio,
     ) -> SlashedCoin {
         let bonded_slashed = staking.bonded * ratio;
-        let unbonded_slashed = staking.unbonded * ratio;
         // no panic: SlashRatio invariant(<= 1.0)
         self.sub_bonded(block_time, block_height, bonded_slashed, staking)
             .unwrap();
-        // no panic: SlashRatio invariant(<= 1.0)
-        staking.unbonded = (staking.unbonded - unbonded_slashed).unwrap();
+        // including the coins in unbonding queue
+        let unbonded_slashed = staking.slash_unbonded(ratio);
         // no panic: Invariant: 4.1 + SlashRatio invariant
         SlashedCoin {
             bonded: bonded_slashed,
@@ -433,6 +988,138 @@ impl StakingTable {
         }
     }
 
+    /// Slash the staking by `slash_ratio` of both bonded and unbonded coins, and jail it if it's
+    /// a council node which is not jailed yet.
+    ///
+    /// Returns the total amount of burned coins, the caller should account for it
+    /// (e.g. put it into rewards pool); `None` if the staking doesn't exist, nothing is written.
+    pub fn slash(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        info: &BeginBlockInfo,
+        addr: &StakedStateAddress,
+        slash_ratio: SlashRatio,
+        reason: PunishmentKind,
+    ) -> Option<Coin> {
+        let mut staking = heap.get(addr)?;
+        if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
+            if !val.is_jailed() {
+                let jailed_until = self.jail_validator(val, info, reason.into());
+                self.participator_stats.remove(addr);
+                set_staking(heap, staking, self.minimal_required_staking);
+                self.metrics
+                    .report(|metrics| metrics.on_jail(addr, jailed_until));
+            }
+        }
+
+        let slashed_coin = self.slash_and_record(
+            heap,
+            info.block_time,
+            info.block_height,
+            addr,
+            slash_ratio,
+            reason,
+        );
+
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+        // no panic: Invariant 4.1
+        Some(slashed_coin.sum().unwrap())
+    }
+
+    /// Jail the validator, permanently if it's a byzantine fault and `permanent_jail` is enabled.
+    fn jail_validator(
+        &self,
+        val: &mut Validator,
+        info: &BeginBlockInfo,
+        reason: JailReason,
+    ) -> Timespec {
+        if self.config.permanent_jail && reason == JailReason::ByzantineFault {
+            val.jail_permanently(info.block_time, info.block_height, reason)
+        } else {
+            val.jail(
+                info.block_time,
+                info.block_height,
+                info.get_unbonding_period(),
+                reason,
+                self.config.jail_escalation_grace_period,
+            )
+        }
+    }
+
+    /// execute slash on the staking and record it in `last_slash`
+    fn slash_and_record(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        block_time: Timespec,
+        block_height: BlockHeight,
+        addr: &StakedStateAddress,
+        ratio: SlashRatio,
+        kind: PunishmentKind,
+    ) -> SlashedCoin {
+        let mut staking = self.get_or_default(heap, addr);
+        #[cfg(debug_assertions)]
+        let total_before = (staking.bonded + staking.total_unbonded().unwrap()).unwrap();
+
+        let slashed_coin = self.execute_slash(block_time, block_height, &mut staking, ratio);
+        let total_slashed_amount = slashed_coin
+            .sum()
+            .expect("sum of bonded and unboned slash amount exceed maximum coin");
+
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            total_before,
+            ((staking.bonded + staking.total_unbonded().unwrap()).unwrap() + total_slashed_amount)
+                .unwrap()
+        );
+
+        // Update the last slash record for query
+        staking.last_slash = Some(SlashRecord {
+            kind,
+            time: block_time,
+            amount: total_slashed_amount,
+        });
+        set_staking(heap, staking, self.minimal_required_staking);
+        self.metrics
+            .report(|metrics| metrics.on_slash(addr, total_slashed_amount));
+        slashed_coin
+    }
+
+    /// Iterate the active validators, order by bonded desc, staking_address
+    pub(crate) fn iter_active_validators<'a>(
+        &'a self,
+        heap: &'a impl GetStaking,
+    ) -> impl Iterator<Item = &'a ValidatorSortKey> + 'a {
+        self.idx_sort.iter().filter(move |key| {
+            // no panic: Invariant 2.1
+            match heap.get(&key.address).unwrap().node_meta {
+                Some(NodeState::CouncilNode(val)) => val.is_active(),
+                _ => false,
+            }
+        })
+    }
+
+    /// Select the validator to be evicted by a new validator when the validator set is full,
+    /// `None` if the set is not full, in which case nobody is evicted.
+    ///
+    /// Tie-break rule: the lowest bonded, then the greatest staking address, i.e. the last one of
+    /// the chosen validators in `idx_sort` order, so the evicted validator is exactly the one
+    /// dropped from the chosen set. It only depends on the ordered index, so it's deterministic
+    /// across nodes.
+    pub fn select_eviction_candidate(&self, heap: &impl GetStaking) -> Option<StakedStateAddress> {
+        self.lowest_in_full_set(heap).map(|key| key.address)
+    }
+
+    /// The sort key of `select_eviction_candidate`
+    pub(crate) fn lowest_in_full_set<'a>(
+        &'a self,
+        heap: &'a impl GetStaking,
+    ) -> Option<&'a ValidatorSortKey> {
+        self.max_validators
+            .checked_sub(1)
+            .and_then(|n| self.iter_active_validators(heap).nth(n))
+    }
+
     fn choose_validators(
         &self,
         heap: &impl GetStaking,
@@ -443,15 +1130,11 @@ impl StakingTable {
             .filter_map(|key| {
                 // no panic: Invariant 2.1
                 let staking = heap.get(&key.address).unwrap();
-                // no panic: Invariant 2.2
-                if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_ref() {
-                    if val.is_active() {
-                        Some((staking.address, staking.bonded.into()))
-                    } else {
-                        None
-                    }
+                // zero if it's not an active council node
+                let power = self.voting_power(&staking);
+                if power > TendermintVotePower::zero() {
+                    Some((staking.address, power))
                 } else {
-                    // FIXME: panic?
                     None
                 }
             })
@@ -459,8 +1142,69 @@ impl StakingTable {
             .collect::<BTreeMap<_, _>>()
     }
 
+    /// Remove the used validator addresses older than `max_evidence_age` from the validator
+    /// records and the index, they are otherwise only pruned when the validator re-joins.
+    /// Called by `begin_block` every `PRUNE_USED_ADDRESSES_INTERVAL` blocks.
+    /// Complexity: O(N)
+    pub fn prune_used_addresses(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        block_time: Timespec,
+        max_evidence_age: Timespec,
+    ) {
+        let is_out_of_date = |ts: &Timespec| ts.saturating_add(max_evidence_age) <= block_time;
+        let to_prune = self
+            .idx_sort
+            .iter()
+            .filter_map(|key| {
+                // no panic: Invariant 2.1
+                let staking = heap.get(&key.address).unwrap();
+                let has_out_of_date = match &staking.node_meta {
+                    Some(NodeState::CouncilNode(val)) => val
+                        .used_validator_addresses
+                        .iter()
+                        .any(|(_, ts)| is_out_of_date(ts)),
+                    _ => false,
+                };
+                if has_out_of_date {
+                    Some(staking)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for mut staking in to_prune.into_iter() {
+            if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
+                for (val_addr, _) in val
+                    .used_validator_addresses
+                    .iter()
+                    .filter(|(_, ts)| is_out_of_date(ts))
+                {
+                    // Invariant 2.1, don't remove the entry of other staking if broken
+                    if self.idx_validator_address.get(val_addr) == Some(&staking.address) {
+                        self.idx_validator_address.remove(val_addr);
+                    } else {
+                        log::error!(
+                            "prune used address {}: {}",
+                            val_addr,
+                            InvariantViolation::IndexDesync(staking.address)
+                        );
+                    }
+                }
+                val.used_validator_addresses
+                    .retain(|(_, ts)| !is_out_of_date(ts));
+            }
+            set_staking(heap, staking, self.minimal_required_staking);
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+    }
+
     /// Cleanup the validator with condition: `block_time > inactive_time + unbonding_period`
-    /// - Remove the validator record from heap
+    /// - Remove the validator record from heap, except the permanently jailed ones, which are
+    ///   kept to reject re-join
     /// - Remove from index structure
     /// Complexity: O(N), Do we need to make it O(log(N))?
     fn cleanup(
@@ -475,7 +1219,7 @@ impl StakingTable {
             .filter_map(|addr| {
                 let staking = heap.get(addr).unwrap();
                 if let Some(NodeState::CouncilNode(val)) = &staking.node_meta {
-                    if val.is_jailed() {
+                    if val.is_jailed() && !val.is_permanently_jailed() {
                         return None;
                     }
                     if let Some(inactive_time) = val.inactive_time {
@@ -506,13 +1250,16 @@ impl StakingTable {
                     assert_eq!(self.idx_validator_address.remove(val_addr), Some(*addr));
                 }
                 assert!(self.idx_sort.remove(&(&staking).into()));
+                self.remove_keypackage(&val.council_node, addr);
                 assert!(self.liveness.remove(addr).is_some());
                 self.participator_stats.remove(addr);
             } else {
                 unreachable!("above filtered to only have inactive validators?")
             }
-            staking.node_meta = None;
-            set_staking(heap, staking, self.minimal_required_staking);
+            if !staking.is_permanently_jailed() {
+                staking.node_meta = None;
+                set_staking(heap, staking, self.minimal_required_staking);
+            }
         }
 
         #[cfg(debug_assertions)]
@@ -586,15 +1333,14 @@ impl StakingTable {
                 // panic: Invariant 2.2
                 if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
                     if !val.is_jailed() {
-                        let jailed_until = val.jail(
-                            info.block_time,
-                            info.block_height,
-                            info.get_unbonding_period(),
-                        );
+                        let jailed_until =
+                            self.jail_validator(val, info, JailReason::ByzantineFault);
                         let maybe_jailed_until = Some(jailed_until);
                         self.participator_stats.remove(addr);
                         slashes.push((*addr, PunishmentKind::ByzantineFault, maybe_jailed_until));
                         set_staking(heap, staking, self.minimal_required_staking);
+                        self.metrics
+                            .report(|metrics| metrics.on_jail(addr, jailed_until));
                     }
                 }
                 // FIXME: else unreachable / panic?
@@ -609,28 +1355,17 @@ impl StakingTable {
         let slashes = slashes
             .into_iter()
             .map(|(addr, kind, maybe_jailed_until)| {
-                let mut staking = heap.get(&addr).unwrap();
-                let slashed_coin = self.slash(
+                let slashed_coin = self.slash_and_record(
+                    heap,
                     info.block_time,
                     info.block_height,
-                    &mut staking,
+                    &addr,
                     match kind {
                         PunishmentKind::NonLive => info.params.get_liveness_slash_percent(),
                         PunishmentKind::ByzantineFault => info.params.get_byzantine_slash_percent(),
                     },
-                );
-
-                let total_slashed_amount = slashed_coin
-                    .sum()
-                    .expect("sum of bonded and unboned slash amount exceed maximum coin");
-
-                // Update the last slash record for query
-                staking.last_slash = Some(SlashRecord {
                     kind,
-                    time: info.block_time,
-                    amount: total_slashed_amount,
-                });
-                set_staking(heap, staking, self.minimal_required_staking);
+                );
 
                 PunishmentOutcome {
                     staking_address: addr,
@@ -661,103 +1396,174 @@ impl StakingTable {
             .collect()
     }
 
-    #[cfg(debug_assertions)]
-    pub(crate) fn check_invariants(&mut self, heap: &impl GetStaking) {
-        self.check_invariant2_1(heap);
-        self.check_invariant2_2(heap);
-        self.check_invariant2_3();
+    /// Audit the invariants, returns the first violation found.
+    pub fn verify_invariants(&self, heap: &impl GetStaking) -> Result<(), InvariantViolation> {
+        self.audit_invariants(heap, &mut |violation| Err(violation))
+    }
+
+    /// Audit the invariants like `verify_invariants`, but go on after a violation is found,
+    /// returns all the violations found, to give operators a complete picture before a repair.
+    pub fn verify_invariants_full(&self, heap: &impl GetStaking) -> Vec<InvariantViolation> {
+        let mut violations = Vec::new();
+        // no error: the reporter never stops the audit
+        let _ = self.audit_invariants(heap, &mut |violation| {
+            violations.push(violation);
+            Ok(())
+        });
+        violations
+    }
+
+    /// Pass every violation found to `report`, stop the audit if it returns an error.
+    fn audit_invariants(
+        &self,
+        heap: &impl GetStaking,
+        report: &mut dyn FnMut(InvariantViolation) -> Result<(), InvariantViolation>,
+    ) -> Result<(), InvariantViolation> {
+        self.verify_invariant2_1(heap, report)?;
+        self.verify_invariant2_2(heap, report)?;
+        self.verify_invariant2_3(report)?;
 
-        self.check_validator_invariant(heap);
+        self.verify_validator_invariant(heap, report)
     }
 
     #[cfg(debug_assertions)]
-    fn check_invariant2_1(&mut self, heap: &impl GetStaking) {
+    pub(crate) fn check_invariants(&self, heap: &impl GetStaking) {
+        if let Err(err) = self.verify_invariants(heap) {
+            panic!("staking table invariant violated: {}", err);
+        }
+    }
+
+    fn verify_invariant2_1(
+        &self,
+        heap: &impl GetStaking,
+        report: &mut dyn FnMut(InvariantViolation) -> Result<(), InvariantViolation>,
+    ) -> Result<(), InvariantViolation> {
         // validator address is the same as stored in heap
         for (val_addr, addr) in self.idx_validator_address.iter() {
-            let staking = heap
-                .get(addr)
-                .expect("idx_validator_address doesn't match heap");
-            let val = staking
-                .node_meta
-                .as_ref()
-                .expect("validator value not exists");
-            if let NodeState::CouncilNode(val) = val {
-                assert!(
-                    val_addr.clone() == val.validator_address()
-                        || val
+            match heap.get(addr).and_then(|staking| staking.node_meta) {
+                Some(NodeState::CouncilNode(val)) => {
+                    if val_addr.clone() != val.validator_address()
+                        && !val
                             .used_validator_addresses
                             .iter()
                             .any(|(addr, _)| addr == val_addr)
-                );
-            } else {
-                unreachable!("FIXME: is this correct for this dynamic check invariant?")
+                    {
+                        report(InvariantViolation::DuplicateValidatorAddress(
+                            val_addr.clone(),
+                            *addr,
+                        ))?;
+                    }
+                }
+                _ => report(InvariantViolation::IndexDesync(*addr))?,
             }
         }
         // addresses in idx_sort are unique
-        assert_eq!(
-            self.idx_sort.len(),
-            self.idx_sort
+        if self.idx_sort.len()
+            != self
+                .idx_sort
                 .iter()
                 .map(|key| key.address)
                 .collect::<BTreeSet<_>>()
                 .len()
-        );
+        {
+            report(InvariantViolation::DuplicateSortKey)?;
+        }
         // bonded coins are the same
         for key in self.idx_sort.iter() {
-            let staking = heap
-                .get(&key.address)
-                .expect("idx_validator_address doesn't match heap");
-            assert_eq!(key.bonded, staking.bonded);
+            match heap.get(&key.address) {
+                Some(staking) if key.bonded != staking.bonded => {
+                    report(InvariantViolation::VotingPowerMismatch(key.address))?
+                }
+                Some(_) => {}
+                None => report(InvariantViolation::IndexDesync(key.address))?,
+            }
         }
+        Ok(())
     }
 
-    #[cfg(debug_assertions)]
-    fn check_invariant2_2(&mut self, heap: &impl GetStaking) {
-        for addr in self.idx_validator_address.values() {
-            heap.get(addr)
-                .expect("idx_validator_address doesn't match heap")
-                .node_meta
-                .as_ref()
-                .expect("validator value not exists");
-        }
-        for key in self.idx_sort.iter() {
-            heap.get(&key.address)
-                .expect("idx_sort doesn't match heap")
-                .node_meta
-                .as_ref()
-                .expect("validator value not exists");
+    fn verify_invariant2_2(
+        &self,
+        heap: &impl GetStaking,
+        report: &mut dyn FnMut(InvariantViolation) -> Result<(), InvariantViolation>,
+    ) -> Result<(), InvariantViolation> {
+        for addr in self
+            .idx_validator_address
+            .values()
+            .chain(self.idx_sort.iter().map(|key| &key.address))
+        {
+            if heap
+                .get(addr)
+                .and_then(|staking| staking.node_meta)
+                .is_none()
+            {
+                report(InvariantViolation::IndexDesync(*addr))?;
+            }
         }
+        Ok(())
     }
 
-    #[cfg(debug_assertions)]
-    fn check_invariant2_3(&mut self) {
-        assert_eq!(
-            self.liveness.keys().collect::<BTreeSet<_>>(),
-            self.idx_sort
-                .iter()
-                .map(|key| &key.address)
-                .collect::<BTreeSet<_>>()
-        );
+    fn verify_invariant2_3(
+        &self,
+        report: &mut dyn FnMut(InvariantViolation) -> Result<(), InvariantViolation>,
+    ) -> Result<(), InvariantViolation> {
+        let sorted = self
+            .idx_sort
+            .iter()
+            .map(|key| &key.address)
+            .collect::<BTreeSet<_>>();
+        for addr in self
+            .liveness
+            .keys()
+            .collect::<BTreeSet<_>>()
+            .symmetric_difference(&sorted)
+        {
+            report(InvariantViolation::IndexDesync(**addr))?;
+        }
+        Ok(())
     }
 
-    #[cfg(debug_assertions)]
-    fn check_validator_invariant(&mut self, heap: &impl GetStaking) {
+    fn verify_validator_invariant(
+        &self,
+        heap: &impl GetStaking,
+        report: &mut dyn FnMut(InvariantViolation) -> Result<(), InvariantViolation>,
+    ) -> Result<(), InvariantViolation> {
         for addr in self.idx_validator_address.values() {
-            match heap
-                .get(addr)
-                .expect("idx_sort doesn't match heap")
-                .node_meta
-                .as_ref()
-                .expect("validator value not exists")
+            let staking = match heap.get(addr) {
+                Some(staking) => staking,
+                None => {
+                    report(InvariantViolation::IndexDesync(*addr))?;
+                    continue;
+                }
+            };
+            // Invariant 4.1
+            if staking
+                .total_unbonded()
+                .and_then(|unbonded| staking.bonded + unbonded)
+                .is_err()
             {
-                NodeState::CouncilNode(v) => {
-                    v.check_invariants();
+                report(InvariantViolation::InvalidBalance(*addr))?;
+            }
+            let val = match staking.node_meta.as_ref() {
+                Some(NodeState::CouncilNode(val)) => val,
+                _ => {
+                    report(InvariantViolation::IndexDesync(*addr))?;
+                    continue;
                 }
-                _ => unreachable!("FIXME?"),
+            };
+            // Invariant 1.1, 1.2, 1.3
+            if val.inactive_time.is_some() != val.inactive_block.is_some()
+                || (val.is_jailed() && val.is_active())
+                || val.jailed_until.is_some() != val.jail_reason.is_some()
+            {
+                report(InvariantViolation::InvalidValidator(*addr))?;
+            }
+            // Invariant 4.2
+            if val.is_active() && staking.bonded < self.minimal_required_staking {
+                report(InvariantViolation::InvalidValidator(*addr))?;
             }
         }
+        Ok(())
     }
-
     /// Get validator pubkey by staking address
     fn get_validator_pubkey(
         &self,
@@ -770,6 +1576,19 @@ impl StakingTable {
         }
     }
 
+    /// Remove the keypackage of the validator from the index,
+    /// keep it if the same keypackage is registered by someone else.
+    pub(crate) fn remove_keypackage(
+        &mut self,
+        council_node: &CouncilNodeMeta,
+        addr: &StakedStateAddress,
+    ) {
+        let hash = keypackage_hash(council_node);
+        if self.idx_keypackage.get(&hash) == Some(addr) {
+            self.idx_keypackage.remove(&hash);
+        }
+    }
+
     /// get staking by staking address
     pub(crate) fn get_or_default(
         &self,
@@ -785,6 +1604,17 @@ impl StakingTable {
     }
 }
 
+/// Hash of the keypackage, used as key of keypackage index
+pub(crate) fn keypackage_hash(council_node: &CouncilNodeMeta) -> H256 {
+    txid_hash(&council_node.node_info.confidential_init.keypackage)
+}
+
+/// Write back the staking read from the same heap in the handler.
+///
+/// The handlers do read-modify-write on the heap, so the txs of the same account in a block
+/// must be executed sequentially against the same buffer, otherwise the later write would
+/// overwrite the earlier one with stale data. Nonce never decreases on the heap, a stale write
+/// is caught by it.
 pub(crate) fn set_staking(
     heap: &mut impl StoreStaking,
     staking: StakedState,
@@ -792,6 +1622,16 @@ pub(crate) fn set_staking(
 ) {
     #[cfg(debug_assertions)]
     staking.check_invariants(_minimal_required_staking);
+    #[cfg(debug_assertions)]
+    let stored_nonce = heap.get(&staking.address).map(|stored| stored.nonce);
+    #[cfg(debug_assertions)]
+    assert!(
+        stored_nonce.map_or(true, |nonce| staking.nonce >= nonce),
+        "stale write of staking {}: nonce {} < {:?}",
+        staking.address,
+        staking.nonce,
+        stored_nonce
+    );
     heap.set_staking(staking)
 }
 
@@ -1,54 +1,221 @@
-use log::warn;
+use std::collections::HashMap;
+
+use log::{error, warn};
+use parity_scale_codec::{Decode, Encode};
 
 use chain_core::common::Timespec;
 use chain_core::init::coin::Coin;
 use chain_core::state::account::{
-    NodeMetadata, NodeState, StakedStateAddress, UnbondTx, UnjailTx, Validator,
+    CouncilNodeMeta, NodeMetadata, NodeState, Nonce, StakedState, StakedStateAddress, UnbondTx,
+    UnjailTx, Validator,
 };
 use chain_core::state::tendermint::{BlockHeight, TendermintValidatorAddress};
-use chain_core::state::validator::NodeJoinRequestTx;
+use chain_core::state::validator::{EditValidatorTx, NodeJoinRequestTx};
+use chain_core::tx::data::output::TxOut;
 use chain_core::tx::fee::Fee;
-use chain_storage::buffer::StoreStaking;
+use chain_core::ChainInfo;
+use chain_storage::buffer::{GetStaking, StoreStaking};
 use mls::{Codec, KeyPackage};
-use ra_client::ENCLAVE_CERT_VERIFIER;
+use ra_client::{AttestedCertVerifier, ENCLAVE_CERT_VERIFIER};
 
-use super::table::{set_staking, StakingTable};
+use super::table::{keypackage_hash, set_staking, FeeDestination, StakingTable};
 use crate::tx_error::{
-    DepositError, NodeJoinError, PublicTxError, UnbondError, UnjailError, WithdrawError,
+    DepositError, NodeJoinError, PublicTxError, RebondError, UnbondError, UnjailError,
+    WithdrawError,
 };
 
-const MAX_USED_VALIDATOR_ADDR: usize = 10;
+/// Information of the current block needed by the transaction handlers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockContext {
+    /// block time of current processing block
+    pub time: Timespec,
+    /// height of current processing block
+    pub height: BlockHeight,
+    /// max evidence age in tendermint consensus parameter
+    pub max_evidence_age: Timespec,
+}
+
+impl BlockContext {
+    pub fn new(time: Timespec, height: BlockHeight, max_evidence_age: Timespec) -> Self {
+        Self {
+            time,
+            height,
+            max_evidence_age,
+        }
+    }
+
+    /// Get unbonding period, which is the same as max evidence age
+    pub fn unbonding_period(&self) -> Timespec {
+        self.max_evidence_age
+    }
+}
+
+impl From<&ChainInfo> for BlockContext {
+    fn from(info: &ChainInfo) -> Self {
+        Self::new(info.block_time, info.block_height, info.max_evidence_age)
+    }
+}
+
+/// Outcome of a successful `NodeJoinTx`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeJoinResult {
+    /// most recent isv_svn
+    pub isv_svn: u16,
+    /// the validator address joined, `None` for community node
+    pub validator_address: Option<TendermintValidatorAddress>,
+    /// whether it re-activated an inactive validator, rather than a fresh insert
+    pub reactivated: bool,
+    /// the lowest-staked validator outbid by the new one when the validator set is full,
+    /// it's replaced in the validator set on the next end block
+    pub evicted: Option<StakedStateAddress>,
+}
+
+/// Outcome of a successful deposit
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DepositResult {
+    pub addr: StakedStateAddress,
+    /// coins added to bonded
+    pub amount: Coin,
+    /// the account is in the middle of unbonding until this time, the deposit is accepted anyway,
+    /// so wallets can warn the user
+    pub unbonding_until: Option<Timespec>,
+}
+
+/// Outcome of a successful unbond
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct UnbondResult {
+    pub addr: StakedStateAddress,
+    /// coins moved from bonded to unbonded
+    pub amount: Coin,
+    pub unbonded_from: Timespec,
+    /// burned from bonded additionally
+    pub fee: Coin,
+    /// the validator became inactive because the remaining bonded is not enough
+    pub deactivated: bool,
+}
+
+/// Outcome of a successful withdraw
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct WithdrawResult {
+    pub addr: StakedStateAddress,
+    /// coins removed from unbonded
+    pub amount: Coin,
+}
+
+/// Outcome of a successful `withdraw_to`
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct WithdrawToResult {
+    pub addr: StakedStateAddress,
+    /// the output to create, the coins removed from unbonded is `output.value`
+    pub output: TxOut,
+}
+
+/// Staking state changes of deposit/unbond/withdraw, for downstream indexers,
+/// the ABCI events are built in `crate::app::staking_event`.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum StakingChange {
+    Deposited(DepositResult),
+    Unbonded(UnbondResult),
+    Withdrawn(WithdrawResult),
+    WithdrawnTo(WithdrawToResult),
+}
+
+impl From<DepositResult> for StakingChange {
+    fn from(result: DepositResult) -> Self {
+        StakingChange::Deposited(result)
+    }
+}
+
+impl From<UnbondResult> for StakingChange {
+    fn from(result: UnbondResult) -> Self {
+        StakingChange::Unbonded(result)
+    }
+}
+
+impl From<WithdrawResult> for StakingChange {
+    fn from(result: WithdrawResult) -> Self {
+        StakingChange::Withdrawn(result)
+    }
+}
+
+impl From<WithdrawToResult> for StakingChange {
+    fn from(result: WithdrawToResult) -> Self {
+        StakingChange::WithdrawnTo(result)
+    }
+}
 
 impl StakingTable {
-    /// Handle `NodeJoinTx`
+    /// Handle `NodeJoinTx`, the keypackage is verified with `ENCLAVE_CERT_VERIFIER`
     pub fn node_join(
         &mut self,
         heap: &mut impl StoreStaking,
-        block_time: Timespec,
-        max_evidence_age: Timespec,
+        ctx: &BlockContext,
         recent_isv_svn: u16,
+        chain_hex_id: u8,
         tx: &NodeJoinRequestTx,
-    ) -> Result<u16, PublicTxError> {
+    ) -> Result<NodeJoinResult, PublicTxError> {
+        self.node_join_with_verifier(
+            heap,
+            ctx,
+            recent_isv_svn,
+            chain_hex_id,
+            tx,
+            &*ENCLAVE_CERT_VERIFIER,
+        )
+    }
+
+    /// Handle `NodeJoinTx`, the keypackage is verified with the provided verifier,
+    /// e.g. for staging enclave certificates; not used with `mock-enclave`.
+    pub fn node_join_with_verifier(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
+        recent_isv_svn: u16,
+        chain_hex_id: u8,
+        tx: &NodeJoinRequestTx,
+        ra_verifier: &impl AttestedCertVerifier,
+    ) -> Result<NodeJoinResult, PublicTxError> {
+        verify_attributes(tx, chain_hex_id)?;
         let mut staking = self.get_or_default(heap, &tx.address);
         if tx.nonce != staking.nonce {
-            return Err(PublicTxError::IncorrectNonce);
+            return Err(PublicTxError::IncorrectNonce {
+                expected: staking.nonce,
+                got: tx.nonce,
+            });
         }
-        if staking.bonded < self.minimal_required_staking {
+        if staking.bonded < self.node_join_minimal_staking(&tx.node_meta) {
             return Err(NodeJoinError::BondedNotEnough.into());
         }
 
         let isv_svn = if cfg!(feature = "mock-enclave") {
             0
         } else {
+            // reported separately from a malformed keypackage, it's usually forgotten
+            if tx.get_keypackage_payload().is_empty() {
+                return Err(NodeJoinError::EmptyKeyPackage.into());
+            }
+            if let NodeMetadata::CouncilNode(cm) = &tx.node_meta {
+                // the keypackage of existing validators are already in the mls tree
+                if self.idx_keypackage.contains_key(&keypackage_hash(cm)) {
+                    return Err(NodeJoinError::KeyPackageAlreadyRegistered.into());
+                }
+            }
             let keypackage = KeyPackage::read_bytes(&tx.get_keypackage_payload())
                 .ok_or(NodeJoinError::KeyPackageDecodeError)?;
             let info = keypackage
-                .verify(&*ENCLAVE_CERT_VERIFIER, block_time)
+                .verify(ra_verifier, ctx.time)
                 .map_err(NodeJoinError::KeyPackageVerifyError)?;
-            // FIXME: more tdbe-related checks that may be observable by abci -- e.g. key not in the mls tree already
+            // FIXME: more tdbe-related checks that may be observable by abci
             info.quote.report_body.isv_svn
         };
 
+        if self.config.reject_svn_downgrade && isv_svn < recent_isv_svn {
+            return Err(NodeJoinError::StaleEnclaveVersion {
+                got: isv_svn,
+                floor: recent_isv_svn,
+            }
+            .into());
+        }
         let new_isv_svn = if isv_svn > recent_isv_svn {
             warn!("more recent version of enclave");
             isv_svn
@@ -56,95 +223,287 @@ impl StakingTable {
             recent_isv_svn
         };
 
-        let val_addr = match &tx.node_meta {
+        let (validator_address, reactivated, evicted) = match &tx.node_meta {
             NodeMetadata::CouncilNode(cm) => {
-                Ok(TendermintValidatorAddress::from(&cm.consensus_pubkey))
+                let (reactivated, evicted) = self.council_node_join(heap, &mut staking, ctx, cm)?;
+                (
+                    Some(TendermintValidatorAddress::from(&cm.consensus_pubkey)),
+                    reactivated,
+                    evicted,
+                )
             }
-            _ => Err(NodeJoinError::WIPNotValidator),
-        }?;
+            NodeMetadata::CommunityNode(info) => {
+                // community nodes don't have validator address, so they are not tracked in the
+                // internal indexes (Invariant 2.4)
+                if staking.node_meta.is_some() {
+                    return Err(NodeJoinError::AlreadyJoined.into());
+                }
+                staking.node_meta = Some(NodeState::CommunityNode(info.clone()));
+                (None, false, None)
+            }
+        };
+        staking.inc_nonce();
+        set_staking(heap, staking, self.minimal_required_staking);
+
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+        self.metrics
+            .report(|metrics| metrics.on_join(&tx.address, validator_address.as_ref()));
+
+        Ok(NodeJoinResult {
+            isv_svn: new_isv_svn,
+            validator_address,
+            reactivated,
+            evicted,
+        })
+    }
+
+    /// Minimal bonded coins required to join as the kind of node
+    pub(crate) fn node_join_minimal_staking(&self, node_meta: &NodeMetadata) -> Coin {
+        match node_meta {
+            NodeMetadata::CouncilNode(_) => self.minimal_required_staking,
+            NodeMetadata::CommunityNode(_) => self
+                .config
+                .community_node_minimal_staking
+                .unwrap_or(self.minimal_required_staking),
+        }
+    }
+
+    /// Join or re-activate council node, and update the related indexes
+    ///
+    /// Returns whether an inactive validator is re-activated,
+    /// and the validator evicted by a fresh insert if any
+    pub(crate) fn council_node_join(
+        &mut self,
+        heap: &impl GetStaking,
+        staking: &mut StakedState,
+        ctx: &BlockContext,
+        council_node: &CouncilNodeMeta,
+    ) -> Result<(bool, Option<StakedStateAddress>), NodeJoinError> {
+        let val_addr = TendermintValidatorAddress::from(&council_node.consensus_pubkey);
         if let Some(NodeState::CouncilNode(val)) = &mut staking.node_meta {
+            // permanently jailed validator is never reactivated, because it can't be unjailed.
             if val.is_jailed() {
-                return Err(NodeJoinError::IsJailed.into());
+                return Err(NodeJoinError::IsJailed);
             }
             if !val.is_active() {
+                // no delegation yet, all the bonded coins are self-bonded
+                if staking.bonded < val.min_self_bonded {
+                    return Err(NodeJoinError::SelfBondedNotEnough);
+                }
                 let old_val_addr = val.validator_address();
                 if old_val_addr != val_addr {
                     // Only check the duplicates if it's not our own.
-                    if self.idx_validator_address.contains_key(&val_addr) {
-                        return Err(NodeJoinError::DuplicateValidatorAddress.into());
+                    if let Some(owner) = self.idx_validator_address.get(&val_addr) {
+                        return Err(NodeJoinError::DuplicateValidatorAddress { owner: *owner });
                     }
 
                     // Add the old one to the used list.
                     let out_of_date = add_old_val_addr(
                         &mut val.used_validator_addresses,
-                        block_time,
+                        ctx.time,
                         &old_val_addr,
-                        MAX_USED_VALIDATOR_ADDR,
-                        max_evidence_age,
+                        self.config.max_used_validator_addr,
+                        ctx.max_evidence_age,
                     )
-                    .ok_or(PublicTxError::NodeJoin(
-                        NodeJoinError::UsedValidatorAddrFull,
-                    ))?;
-
-                    for used_addr in out_of_date.into_iter() {
-                        assert_eq!(
-                            self.idx_validator_address.remove(&used_addr),
-                            Some(tx.address)
+                    .map_err(|retry_after| NodeJoinError::UsedValidatorAddrFull { retry_after })?;
+
+                    // check before removing anything, so the indexes are untouched on error
+                    if out_of_date.iter().any(|used_addr| {
+                        self.idx_validator_address.get(used_addr) != Some(&staking.address)
+                    }) {
+                        error!(
+                            "validator address index out of sync with staking: {}",
+                            staking.address
                         );
+                        return Err(NodeJoinError::IndexDesync);
+                    }
+                    for used_addr in out_of_date.iter() {
+                        self.idx_validator_address.remove(used_addr);
                     }
-                    self.idx_validator_address.insert(val_addr, tx.address);
+                    self.idx_validator_address.insert(val_addr, staking.address);
                 }
-                val.council_node = match &tx.node_meta {
-                    NodeMetadata::CouncilNode(cm) => cm.clone(),
-                    _ => unreachable!("FIXME"),
-                };
+                self.remove_keypackage(&val.council_node, &staking.address);
+                self.idx_keypackage
+                    .insert(keypackage_hash(council_node), staking.address);
+                val.council_node = council_node.clone();
                 val.inactive_time = None;
                 val.inactive_block = None;
+                Ok((true, None))
             } else {
-                return Err(NodeJoinError::AlreadyJoined.into());
+                Err(NodeJoinError::AlreadyJoined)
             }
         } else {
-            if self.idx_validator_address.contains_key(&val_addr) {
-                return Err(NodeJoinError::DuplicateValidatorAddress.into());
+            // the owner could be ourselves, if it's the used address of a previous validator
+            if let Some(owner) = self.idx_validator_address.get(&val_addr) {
+                return Err(NodeJoinError::DuplicateValidatorAddress { owner: *owner });
             }
 
-            // insert
-            staking.node_meta = match &tx.node_meta {
-                NodeMetadata::CouncilNode(cm) => {
-                    Some(NodeState::CouncilNode(Validator::new(cm.clone())))
-                }
-                _ => {
-                    // FIXME
-                    None
-                }
+            let evicted = match self.lowest_in_full_set(heap) {
+                // not full
+                None if self.max_validators > 0 => None,
+                // full, outbid the lowest-staked validator in the set
+                Some(lowest) if staking.bonded > lowest.bonded => Some(lowest.address),
+                _ => return Err(NodeJoinError::ValidatorSetFull),
             };
-            self.insert_validator(&staking).expect("new validator");
+
+            // insert, replace the community node record if any
+            let mut val = Validator::new(council_node.clone());
+            val.min_self_bonded = self.minimal_required_staking;
+            staking.node_meta = Some(NodeState::CouncilNode(val));
+            if let Err(err) = self.insert_validator(staking) {
+                error!(
+                    "failed to insert validator {} into the indexes: {:?}",
+                    staking.address, err
+                );
+                return Err(NodeJoinError::IndexDesync);
+            }
+            Ok((false, evicted))
         }
+    }
+
+    /// Handle `EditValidatorTx`, update the informational metadata and the authorized delegate of
+    /// a council node in place, it doesn't affect the validator set.
+    pub fn edit_validator(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        tx: &EditValidatorTx,
+    ) -> Result<(), PublicTxError> {
+        let mut staking = self.get_or_default(heap, &tx.address);
+        if tx.nonce != staking.nonce {
+            return Err(PublicTxError::IncorrectNonce {
+                expected: staking.nonce,
+                got: tx.nonce,
+            });
+        }
+
+        if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
+            if val.is_jailed() {
+                return Err(NodeJoinError::IsJailed.into());
+            }
+            // changing the consensus pubkey would change the validator address
+            if val.council_node.consensus_pubkey != tx.consensus_pubkey {
+                return Err(NodeJoinError::ConsensusKeyImmutable.into());
+            }
+            val.council_node.node_info.name = tx.name.clone();
+            val.council_node.node_info.security_contact = tx.security_contact.clone();
+            val.authorized_delegate = tx.authorized_delegate;
+        } else {
+            return Err(NodeJoinError::NotCouncilNode.into());
+        }
+
         staking.inc_nonce();
         set_staking(heap, staking, self.minimal_required_staking);
 
         #[cfg(debug_assertions)]
         self.check_invariants(heap);
+        Ok(())
+    }
+
+    /// Re-activate an inactive (not jailed) council node with the existing keypackage and
+    /// consensus key, it's like re-joining with the same `CouncilNodeMeta` in `node_join`,
+    /// without re-verifying the keypackage.
+    pub fn reactivate(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        addr: &StakedStateAddress,
+        nonce: Nonce,
+    ) -> Result<(), PublicTxError> {
+        let mut staking = self.get_or_default(heap, addr);
+        if nonce != staking.nonce {
+            return Err(PublicTxError::IncorrectNonce {
+                expected: staking.nonce,
+                got: nonce,
+            });
+        }
+        if staking.bonded < self.minimal_required_staking {
+            return Err(NodeJoinError::BondedNotEnough.into());
+        }
 
-        Ok(new_isv_svn)
+        let validator_address =
+            if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
+                if val.is_jailed() {
+                    return Err(NodeJoinError::IsJailed.into());
+                }
+                if val.is_active() {
+                    return Err(NodeJoinError::AlreadyJoined.into());
+                }
+                // no delegation yet, all the bonded coins are self-bonded
+                if staking.bonded < val.min_self_bonded {
+                    return Err(NodeJoinError::SelfBondedNotEnough.into());
+                }
+                // the validator address and keypackage are unchanged, so the indexes are untouched
+                val.inactive_time = None;
+                val.inactive_block = None;
+                val.validator_address()
+            } else {
+                return Err(NodeJoinError::NotCouncilNode.into());
+            };
+
+        staking.inc_nonce();
+        set_staking(heap, staking, self.minimal_required_staking);
+
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+        self.metrics
+            .report(|metrics| metrics.on_join(addr, Some(&validator_address)));
+        Ok(())
     }
 
     /// Handle `UnjailTx`
     pub fn unjail(
         &mut self,
         heap: &mut impl StoreStaking,
-        block_time: Timespec,
+        ctx: &BlockContext,
+        tx: &UnjailTx,
+    ) -> Result<(), PublicTxError> {
+        self.unjail_signed_by(heap, ctx, tx, &tx.address)
+    }
+
+    /// Handle `UnjailTx` signed by `signer`, which should be either the staking address itself,
+    /// or the authorized delegate of the validator.
+    pub fn unjail_signed_by(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
         tx: &UnjailTx,
+        signer: &StakedStateAddress,
     ) -> Result<(), PublicTxError> {
         let mut staking = self.get_or_default(heap, &tx.address);
+        if signer != &tx.address {
+            match &staking.node_meta {
+                Some(NodeState::CouncilNode(val)) if val.authorized_delegate == Some(*signer) => {}
+                _ => return Err(PublicTxError::StakingWitnessNotMatch),
+            }
+        }
         if tx.nonce != staking.nonce {
-            return Err(PublicTxError::IncorrectNonce);
+            if let (Some(window), Some(NodeState::CouncilNode(val))) =
+                (self.config.idempotent_unjail_window, &staking.node_meta)
+            {
+                // a retry of the already applied unjail tx
+                let recently_unjailed = val
+                    .last_unjailed_at
+                    .map_or(false, |at| ctx.time <= at.saturating_add(window));
+                if !val.is_jailed()
+                    && recently_unjailed
+                    && tx.nonce.checked_add(1) == Some(staking.nonce)
+                {
+                    return Ok(());
+                }
+            }
+            return Err(PublicTxError::IncorrectNonce {
+                expected: staking.nonce,
+                got: tx.nonce,
+            });
         }
 
         if let Some(NodeState::CouncilNode(val)) = staking.node_meta.as_mut() {
+            if val.is_permanently_jailed() {
+                return Err(UnjailError::PermanentlyJailed.into());
+            }
             if let Some(jailed_until) = val.jailed_until {
-                if block_time >= jailed_until {
-                    val.unjail();
+                if ctx.time >= jailed_until {
+                    val.unjail(ctx.time);
                     staking.inc_nonce();
                     set_staking(heap, staking, self.minimal_required_staking);
 
@@ -158,7 +517,7 @@ impl StakingTable {
                 Err(UnjailError::NotJailed.into())
             }
         } else {
-            Err(UnjailError::NotJailed.into())
+            Err(UnjailError::NotAValidator.into())
         }
     }
 
@@ -167,17 +526,107 @@ impl StakingTable {
     pub fn deposit(
         &mut self,
         heap: &mut impl StoreStaking,
+        block_time: Timespec,
         addr: &StakedStateAddress,
         amount: Coin,
-    ) -> Result<(), DepositError> {
+    ) -> Result<DepositResult, DepositError> {
+        self.deposit_locked(heap, block_time, addr, amount, None)
+    }
+
+    /// Handle deposit like `deposit`, and lock the deposited amount from unbonding until
+    /// `locked_until` if it's set, e.g. for staking-incentive programs.
+    /// The lock only applies to the deposited amount, the other bonded coins of the account are
+    /// not affected, so the others can't freeze an account by depositing into it.
+    pub fn deposit_locked(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        block_time: Timespec,
+        addr: &StakedStateAddress,
+        amount: Coin,
+        locked_until: Option<Timespec>,
+    ) -> Result<DepositResult, DepositError> {
         let mut staking = self.get_or_default(heap, addr);
         if staking.is_jailed() {
             return Err(DepositError::IsJailed);
         }
+        let unbonding_until = unbonding_until(&staking, block_time);
+        if let Some(locked_until) = locked_until {
+            staking.add_bond_lock(amount, locked_until, block_time)?;
+        }
 
         self.add_bonded(amount, &mut staking)?;
         set_staking(heap, staking, self.minimal_required_staking);
 
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+        Ok(DepositResult {
+            addr: *addr,
+            amount,
+            unbonding_until,
+        })
+    }
+
+    /// Handle deposit like `deposit`, but only to an existing staking account, rather than creating
+    /// a new one, to catch deposits to mistyped addresses.
+    pub fn deposit_existing(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        block_time: Timespec,
+        addr: &StakedStateAddress,
+        amount: Coin,
+    ) -> Result<DepositResult, DepositError> {
+        if heap.get(addr).is_none() {
+            return Err(DepositError::AccountNotFound);
+        }
+        self.deposit(heap, block_time, addr, amount)
+    }
+
+    /// Handle a batch of deposits, the amounts to the same address are summed up and applied once.
+    /// Either all the deposits are applied, or none of them is, the first error is returned together
+    /// with the offending address.
+    pub fn deposit_batch(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        deposits: &[(StakedStateAddress, Coin)],
+    ) -> Result<(), DepositError> {
+        let in_batch = |address: StakedStateAddress, err: DepositError| DepositError::InBatch {
+            address,
+            source: Box::new(err),
+        };
+
+        // group by address, keep the order of first occurrence
+        let mut groups: Vec<(StakedStateAddress, Coin)> = vec![];
+        let mut index = HashMap::new();
+        for (addr, amount) in deposits.iter() {
+            match index.get(addr) {
+                Some(&i) => {
+                    groups[i].1 =
+                        (groups[i].1 + *amount).map_err(|err| in_batch(*addr, err.into()))?;
+                }
+                None => {
+                    index.insert(*addr, groups.len());
+                    groups.push((*addr, *amount));
+                }
+            }
+        }
+
+        // validate all before modifying anything
+        let mut stakings = Vec::with_capacity(groups.len());
+        for (addr, amount) in groups.iter() {
+            let staking = self.get_or_default(heap, addr);
+            if staking.is_jailed() {
+                return Err(in_batch(*addr, DepositError::IsJailed));
+            }
+            (staking.bonded + *amount).map_err(|err| in_batch(*addr, err.into()))?;
+            stakings.push(staking);
+        }
+
+        for (mut staking, (_, amount)) in stakings.into_iter().zip(groups.into_iter()) {
+            // no panic: checked above
+            self.add_bonded(amount, &mut staking).unwrap();
+            set_staking(heap, staking, self.minimal_required_staking);
+        }
+
         #[cfg(debug_assertions)]
         self.check_invariants(heap);
         Ok(())
@@ -187,79 +636,327 @@ impl StakingTable {
     pub fn unbond(
         &mut self,
         heap: &mut impl StoreStaking,
-        unbonding_period: Timespec,
-        block_time: Timespec,
-        block_height: BlockHeight,
+        ctx: &BlockContext,
+        tx: &UnbondTx,
+        fee: Fee,
+    ) -> Result<UnbondResult, PublicTxError> {
+        self.execute_unbond(
+            heap,
+            ctx.time.saturating_add(ctx.unbonding_period()),
+            ctx,
+            tx,
+            fee,
+            Coin::zero(),
+        )
+    }
+
+    /// Handle unbond tx without waiting for the unbonding period,
+    /// a penalty of `tx.value / immediate_unbond_penalty_divisor` is burned from bonded on top of the fee.
+    ///
+    /// Returns the unbond result and the burned penalty, the caller should account for it.
+    pub fn unbond_immediate(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
         tx: &UnbondTx,
         fee: Fee,
-    ) -> Result<Timespec, PublicTxError> {
+    ) -> Result<(UnbondResult, Coin), PublicTxError> {
+        // no panic: divisor is not zero
+        let penalty = (tx.value / self.config.immediate_unbond_penalty_divisor).unwrap();
+        let result = self.execute_unbond(heap, ctx.time, ctx, tx, fee, penalty)?;
+        Ok((result, penalty))
+    }
+
+    fn execute_unbond(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        unbonded_from: Timespec,
+        ctx: &BlockContext,
+        tx: &UnbondTx,
+        fee: Fee,
+        penalty: Coin,
+    ) -> Result<UnbondResult, PublicTxError> {
         let mut staking = self.get_or_default(heap, &tx.from_staked_account);
         if tx.nonce != staking.nonce {
-            return Err(PublicTxError::IncorrectNonce);
+            return Err(PublicTxError::IncorrectNonce {
+                expected: staking.nonce,
+                got: tx.nonce,
+            });
         }
-        if staking.is_jailed() {
+        // the permanently jailed ones can unbond the coins left after the slash
+        if staking.is_jailed() && !staking.is_permanently_jailed() {
             return Err(UnbondError::IsJailed.into());
         }
         let fee_amount = fee.to_coin();
         if tx.value == Coin::zero() {
             return Err(UnbondError::ZeroValue.into());
         }
-        let unbonded = (staking.unbonded + tx.value).map_err(UnbondError::CoinError)?;
-        self.sub_bonded(
-            block_time,
-            block_height,
-            (tx.value + fee_amount).map_err(UnbondError::CoinError)?,
-            &mut staking,
-        )
-        .map_err(UnbondError::CoinError)?;
-        staking.unbonded = unbonded;
+        if tx.value < self.config.min_unbond_amount {
+            return Err(UnbondError::BelowMinimum {
+                min: self.config.min_unbond_amount,
+            }
+            .into());
+        }
+        #[cfg(debug_assertions)]
+        let total_before = staking.bonded + staking.total_unbonded().unwrap();
+        // check overflow before modifying
+        (staking
+            .total_unbonded()
+            .and_then(|unbonded| unbonded + tx.value))
+        .map_err(|_| UnbondError::UnbondedOverflow)?;
+        let burned = (fee_amount + penalty).map_err(|_| UnbondError::FeeOverflow)?;
+        let requested = (tx.value + burned).map_err(|_| UnbondError::FeeOverflow)?;
+        if requested > staking.bonded {
+            return Err(UnbondError::InsufficientBonded {
+                available: staking.bonded,
+                requested,
+            }
+            .into());
+        }
+        if let Some(until) = staking.bond_unlocked_at(requested, ctx.time) {
+            return Err(UnbondError::BondLocked { until }.into());
+        }
+        let treasury = match self.config.fee_destination {
+            FeeDestination::Treasury(treasury) if fee_amount > Coin::zero() => Some(treasury),
+            _ => None,
+        };
+        if let Some(treasury) = &treasury {
+            (self.get_or_default(heap, treasury).bonded + fee_amount)
+                .map_err(UnbondError::CoinError)?;
+        }
+        let active_before = is_active_validator(&staking);
+        // unreachable: `requested <= staking.bonded` is checked above
+        self.sub_bonded(ctx.time, ctx.height, requested, &mut staking)
+            .map_err(|_| UnbondError::BondedUnderflow)?;
+        let deactivated = active_before && !is_active_validator(&staking);
+        // the coins unbonded before keep their own `unbonded_from`
+        staking
+            .add_unbonded(tx.value, unbonded_from)
+            .map_err(|_| UnbondError::UnbondedOverflow)?;
+        // fee and penalty leave the account, others are conserved.
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            total_before,
+            (staking.bonded + staking.total_unbonded().unwrap()).and_then(|total| total + burned)
+        );
 
-        let unbonded_from = block_time.saturating_add(unbonding_period);
-        staking.unbonded_from = unbonded_from;
         staking.inc_nonce();
         set_staking(heap, staking, self.minimal_required_staking);
+        // credit after the unbonding account is stored, in case the treasury is the same account
+        if let Some(treasury) = treasury {
+            let mut treasury = self.get_or_default(heap, &treasury);
+            // no panic: checked above, the unbonding account's bonded only decreased
+            self.add_bonded(fee_amount, &mut treasury).unwrap();
+            set_staking(heap, treasury, self.minimal_required_staking);
+        }
         #[cfg(debug_assertions)]
         self.check_invariants(heap);
-        Ok(unbonded_from)
+        self.metrics
+            .report(|metrics| metrics.on_unbond(&tx.from_staked_account, tx.value));
+        Ok(UnbondResult {
+            addr: tx.from_staked_account,
+            amount: tx.value,
+            unbonded_from,
+            fee: fee_amount,
+            deactivated,
+        })
     }
 
-    /// Handle withdraw tx
-    /// Enclave validation is done in enclave, only incomplete check here.
-    pub fn withdraw(
+    /// Move unbonded coins back to bonded, it's not required to wait for `unbonded_from`.
+    pub fn rebond(
         &mut self,
         heap: &mut impl StoreStaking,
-        block_time: Timespec,
         addr: &StakedStateAddress,
         amount: Coin,
-    ) -> Result<(), WithdrawError> {
+        tx_nonce: Nonce,
+    ) -> Result<(), PublicTxError> {
         let mut staking = self.get_or_default(heap, addr);
+        if tx_nonce != staking.nonce {
+            return Err(PublicTxError::IncorrectNonce {
+                expected: staking.nonce,
+                got: tx_nonce,
+            });
+        }
         if staking.is_jailed() {
-            return Err(WithdrawError::IsJailed);
+            return Err(RebondError::IsJailed.into());
+        }
+        if amount == Coin::zero() {
+            return Err(RebondError::ZeroValue.into());
         }
-        if block_time < staking.unbonded_from {
-            return Err(WithdrawError::InUnbondingPeriod);
+        let total_unbonded = staking.total_unbonded().map_err(RebondError::CoinError)?;
+        if amount > total_unbonded {
+            return Err(RebondError::UnbondedNotEnough(total_unbonded, amount).into());
         }
+        #[cfg(debug_assertions)]
+        let total_before = (staking.bonded + total_unbonded).unwrap();
+
+        self.add_bonded(amount, &mut staking)
+            .map_err(RebondError::CoinError)?;
+        // the latest unbonded coins are rebonded first, since they mature last
+        // no panic: checked above
+        staking.sub_unbonded_latest(amount).unwrap();
+
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            total_before,
+            (staking.bonded + staking.total_unbonded().unwrap()).unwrap()
+        );
+        staking.inc_nonce();
+        set_staking(heap, staking, self.minimal_required_staking);
+        #[cfg(debug_assertions)]
+        self.check_invariants(heap);
+        Ok(())
+    }
+
+    /// Handle withdraw tx
+    /// Enclave validation is done in enclave, only incomplete check here.
+    pub fn withdraw(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
+        addr: &StakedStateAddress,
+        amount: Coin,
+    ) -> Result<WithdrawResult, WithdrawError> {
+        let mut staking = self.get_or_default(heap, addr);
+        check_withdrawable(&staking, ctx.time)?;
         if staking.unbonded != amount {
             return Err(WithdrawError::UnbondedSanityCheck(staking.unbonded, amount));
         }
-        staking.unbonded = Coin::zero();
+        // no panic: checked above
+        staking.sub_unbonded(amount).unwrap();
+        Ok(self.execute_withdraw(heap, staking, amount))
+    }
+
+    /// Withdraw part of the unbonded coins, the remaining ones keep the same `unbonded_from`.
+    pub fn withdraw_partial(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
+        addr: &StakedStateAddress,
+        amount: Coin,
+    ) -> Result<WithdrawResult, WithdrawError> {
+        let mut staking = self.get_or_default(heap, addr);
+        check_withdrawable(&staking, ctx.time)?;
+        if amount == Coin::zero() {
+            return Err(WithdrawError::ZeroValue);
+        }
+        if amount > staking.unbonded {
+            return Err(WithdrawError::UnbondedNotEnough(staking.unbonded, amount));
+        }
+        // no panic: checked above
+        staking.sub_unbonded(amount).unwrap();
+        Ok(self.execute_withdraw(heap, staking, amount))
+    }
+
+    /// Withdraw all the matured unbonded coins, including the matured ones in the unbonding queue,
+    /// the amount is read from the current state rather than supplied by the client, so it can't
+    /// be stale.
+    pub fn withdraw_all(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
+        addr: &StakedStateAddress,
+    ) -> Result<WithdrawResult, WithdrawError> {
+        let mut staking = self.get_or_default(heap, addr);
+        check_withdrawable(&staking, ctx.time)?;
+        // no panic: Invariant 4.1
+        let amount = staking.sub_matured_unbonded(ctx.time).unwrap();
+        if amount == Coin::zero() {
+            return Err(WithdrawError::ZeroValue);
+        }
+        Ok(self.execute_withdraw(heap, staking, amount))
+    }
+
+    /// Withdraw like `withdraw` and send all the coins to `output` in one step, the result carries
+    /// the output to create.
+    pub fn withdraw_to(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        ctx: &BlockContext,
+        addr: &StakedStateAddress,
+        amount: Coin,
+        output: TxOut,
+    ) -> Result<WithdrawToResult, WithdrawError> {
+        if output.value != amount {
+            return Err(WithdrawError::OutputValueMismatch(amount, output.value));
+        }
+        let WithdrawResult { addr, .. } = self.withdraw(heap, ctx, addr, amount)?;
+        Ok(WithdrawToResult { addr, output })
+    }
+
+    /// Caller should remove the withdrawn `amount` from `staking`
+    fn execute_withdraw(
+        &mut self,
+        heap: &mut impl StoreStaking,
+        mut staking: StakedState,
+        amount: Coin,
+    ) -> WithdrawResult {
+        let addr = staking.address;
         staking.inc_nonce();
         set_staking(heap, staking, self.minimal_required_staking);
         #[cfg(debug_assertions)]
         self.check_invariants(heap);
-        Ok(())
+        self.metrics
+            .report(|metrics| metrics.on_withdraw(&addr, amount));
+        WithdrawResult { addr, amount }
     }
 }
 
+/// Check the network identifier in the attributes of node join tx
+pub(crate) fn verify_attributes(
+    tx: &NodeJoinRequestTx,
+    expected_chain_hex_id: u8,
+) -> Result<(), NodeJoinError> {
+    if tx.attributes.chain_hex_id != expected_chain_hex_id {
+        return Err(NodeJoinError::WrongChainHexId);
+    }
+    Ok(())
+}
+
+/// The time until which the account is in the middle of unbonding, i.e. the latest unbonded coins
+/// are not withdrawable yet at `block_time`
+fn unbonding_until(staking: &StakedState, block_time: Timespec) -> Option<Timespec> {
+    let until = staking
+        .unbonding_queue
+        .last()
+        .map_or(staking.unbonded_from, |(_, time)| *time);
+    // Invariant 4.3: unbonded is not zero if there are coins in the unbonding queue
+    if staking.unbonded > Coin::zero() && block_time < until {
+        Some(until)
+    } else {
+        None
+    }
+}
+
+fn is_active_validator(staking: &StakedState) -> bool {
+    match &staking.node_meta {
+        Some(NodeState::CouncilNode(val)) => val.is_active(),
+        _ => false,
+    }
+}
+
+fn check_withdrawable(staking: &StakedState, block_time: Timespec) -> Result<(), WithdrawError> {
+    if staking.is_jailed() && !staking.is_permanently_jailed() {
+        return Err(WithdrawError::IsJailed);
+    }
+    if block_time < staking.unbonded_from {
+        return Err(WithdrawError::InUnbondingPeriod);
+    }
+    Ok(())
+}
+
 /// Return out of date addresses if success (not exceeds max bound),
-/// otherwise None
-fn add_old_val_addr(
+/// otherwise the earliest time when one of the used addresses gets out of date
+///
+/// The used addresses are kept sorted by `(timestamp, address)`, so the serialized staking
+/// state doesn't depend on the insertion history.
+pub(crate) fn add_old_val_addr(
     used: &mut Vec<(TendermintValidatorAddress, Timespec)>,
     block_time: Timespec,
     old_val_addr: &TendermintValidatorAddress,
     max_bound: usize,
     max_evidence_age: Timespec,
-) -> Option<Vec<TendermintValidatorAddress>> {
+) -> Result<Vec<TendermintValidatorAddress>, Timespec> {
     // Move the out of date ones out
     let out_of_date = used
         .iter()
@@ -275,8 +972,15 @@ fn add_old_val_addr(
     if used.len() - out_of_date.len() < max_bound {
         used.retain(|(_, ts)| ts.saturating_add(max_evidence_age) > block_time);
         used.push((old_val_addr.clone(), block_time));
-        Some(out_of_date)
+        used.sort_by(|(addr1, ts1), (addr2, ts2)| (ts1, addr1).cmp(&(ts2, addr2)));
+        Ok(out_of_date)
     } else {
-        None
+        // empty only if the bound is zero, then there's never room
+        Err(used
+            .iter()
+            .map(|(_, ts)| ts.saturating_add(max_evidence_age))
+            .filter(|expiry| *expiry > block_time)
+            .min()
+            .unwrap_or(Timespec::MAX))
     }
 }
@@ -1,16 +1,17 @@
 use crate::enclave_bridge::EnclaveProxy;
-use crate::staking::StakingTable;
+use crate::staking::{BlockContext, StakingTable};
 use crate::tx_error::PublicTxError;
 use chain_core::common::Timespec;
 use chain_core::init::coin::Coin;
-use chain_core::state::account::{
-    CouncilNodeMeta, NodeMetadata, StakedStateAddress, StakedStateOpAttributes,
-};
+use chain_core::state::account::{NodeMetadata, StakedStateAddress, StakedStateOpAttributes};
+use chain_core::state::validator::NodeJoinWitnessError;
 use chain_core::tx::data::input::{TxoPointer, TxoSize};
 use chain_core::tx::fee::Fee;
 use chain_core::tx::{TransactionId, TxEnclaveAux, TxObfuscated, TxPublicAux};
 use chain_storage::buffer::{GetKV, GetStaking, StoreStaking};
-use chain_tx_validation::{verify_unjailed, witness::verify_tx_recover_address, ChainInfo, Error};
+use chain_tx_validation::{
+    verify_unbondable, verify_unjailed, witness::verify_tx_recover_address, ChainInfo, Error,
+};
 use enclave_protocol::{IntraEnclaveRequest, IntraEnclaveResponseOk, SealedLog};
 
 pub enum TxAction {
@@ -120,11 +121,12 @@ pub enum TxPublicAction {
     },
     NodeJoin {
         address: StakedStateAddress,
-        council_node: CouncilNodeMeta,
+        node_meta: NodeMetadata,
         // most recent isv_svn
         isv_svn: u16,
     },
     Unjail(StakedStateAddress),
+    EditValidator(StakedStateAddress),
 }
 
 impl TxPublicAction {
@@ -135,22 +137,26 @@ impl TxPublicAction {
             unbonded_from,
         }
     }
-    fn node_join(address: StakedStateAddress, council_node: CouncilNodeMeta, isv_svn: u16) -> Self {
+    fn node_join(address: StakedStateAddress, node_meta: NodeMetadata, isv_svn: u16) -> Self {
         Self::NodeJoin {
             address,
-            council_node,
+            node_meta,
             isv_svn,
         }
     }
     fn unjail(staking_address: StakedStateAddress) -> Self {
         Self::Unjail(staking_address)
     }
+    fn edit_validator(staking_address: StakedStateAddress) -> Self {
+        Self::EditValidator(staking_address)
+    }
 
     pub fn fee(&self) -> Fee {
         match self {
             Self::Unbond { fee, .. } => *fee,
             Self::NodeJoin { .. } => Fee::new(Coin::zero()),
             Self::Unjail(_) => Fee::new(Coin::zero()),
+            Self::EditValidator(_) => Fee::new(Coin::zero()),
         }
     }
 
@@ -159,6 +165,7 @@ impl TxPublicAction {
             Self::Unbond { unbond, .. } => Some(unbond.0),
             Self::NodeJoin { address, .. } => Some(*address),
             Self::Unjail(staking_address) => Some(*staking_address),
+            Self::EditValidator(staking_address) => Some(*staking_address),
         }
     }
 }
@@ -255,7 +262,7 @@ pub fn verify_enclave_tx<T: EnclaveProxy>(
             let account_address =
                 verify_tx_recover_address(&witness, &txid).map_err(|_| Error::EcdsaCrypto)?;
             let account = trie.get(&account_address).ok_or(Error::AccountNotFound)?;
-            verify_unjailed(&account)?;
+            verify_unbondable(&account)?;
             let withdraw_amount = account.unbonded;
             let response = tx_validator.process_request(
                 IntraEnclaveRequest::new_validate_withdraw(txaux.clone(), *extra_info, account),
@@ -309,14 +316,14 @@ pub fn process_public_tx(
             if address != maintx.from_staked_account {
                 return Err(PublicTxError::StakingWitnessNotMatch);
             }
-            let unbonded_from = staking_table.unbond(
-                staking_store,
-                chain_info.get_unbonding_period(),
-                chain_info.block_time,
-                chain_info.block_height,
-                &maintx,
-                chain_info.min_fee_computed,
-            )?;
+            let unbonded_from = staking_table
+                .unbond(
+                    staking_store,
+                    &BlockContext::from(chain_info),
+                    &maintx,
+                    chain_info.min_fee_computed,
+                )?
+                .unbonded_from;
 
             Ok(TxPublicAction::unbond(
                 chain_info.min_fee_computed,
@@ -326,37 +333,47 @@ pub fn process_public_tx(
         }
         // TODO: delay checking witness, as address is contained in Tx?
         TxPublicAux::UnjailTx(maintx, witness) => {
-            let address = verify_tx_recover_address(&witness, &maintx.id())?;
-            if address != maintx.address {
-                return Err(PublicTxError::StakingWitnessNotMatch);
-            }
-
-            staking_table.unjail(staking_store, chain_info.block_time, maintx)?;
+            // the signer could be the authorized delegate, checked in `unjail_signed_by`
+            let signer = verify_tx_recover_address(&witness, &maintx.id())?;
+            staking_table.unjail_signed_by(
+                staking_store,
+                &BlockContext::from(chain_info),
+                maintx,
+                &signer,
+            )?;
 
-            Ok(TxPublicAction::unjail(address))
+            Ok(TxPublicAction::unjail(maintx.address))
         }
         // TODO: delay checking witness, as address is contained in Tx?
         TxPublicAux::NodeJoinTx(maintx, witness) => {
-            let address = verify_tx_recover_address(&witness, &maintx.id())?;
-            if address != maintx.address {
-                return Err(PublicTxError::StakingWitnessNotMatch);
-            }
-            let isv_svn = staking_table.node_join(
+            maintx.verify_witness(witness).map_err(|err| match err {
+                NodeJoinWitnessError::Verify(err) => PublicTxError::StakingWitnessVerify(err),
+                NodeJoinWitnessError::AddressNotMatch => PublicTxError::StakingWitnessNotMatch,
+            })?;
+            let address = maintx.address;
+            let result = staking_table.node_join(
                 staking_store,
-                chain_info.block_time,
-                chain_info.max_evidence_age,
+                &BlockContext::from(chain_info),
                 enclave_isv_svn,
+                chain_info.chain_hex_id,
                 maintx,
             )?;
 
             Ok(TxPublicAction::node_join(
                 address,
-                match &maintx.node_meta {
-                    NodeMetadata::CouncilNode(cm) => cm.clone(),
-                    _ => unreachable!("FIXME?"),
-                },
-                isv_svn,
+                maintx.node_meta.clone(),
+                result.isv_svn,
             ))
         }
+        // TODO: delay checking witness, as address is contained in Tx?
+        TxPublicAux::EditValidatorTx(maintx, witness) => {
+            let address = verify_tx_recover_address(&witness, &maintx.id())?;
+            if address != maintx.address {
+                return Err(PublicTxError::StakingWitnessNotMatch);
+            }
+            staking_table.edit_validator(staking_store, maintx)?;
+
+            Ok(TxPublicAction::edit_validator(address))
+        }
     }
 }
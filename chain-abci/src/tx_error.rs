@@ -1,4 +1,6 @@
+use chain_core::common::Timespec;
 use chain_core::init::coin::{Coin, CoinError};
+use chain_core::state::account::{Nonce, StakedStateAddress};
 use mls::keypackage;
 
 #[derive(thiserror::Error, Debug)]
@@ -23,14 +25,40 @@ pub enum PublicTxError {
     StakingWitnessVerify(#[from] secp256k1::Error),
     #[error("staking witness and address don't match")]
     StakingWitnessNotMatch,
-    #[error("tx nonce don't match staking state")]
-    IncorrectNonce,
+    #[error("tx nonce {got} don't match staking state, expected: {expected}")]
+    IncorrectNonce { expected: Nonce, got: Nonce },
     #[error("unjail tx process failed: {0}")]
     Unjail(#[from] UnjailError),
     #[error("node join tx process failed: {0}")]
     NodeJoin(#[from] NodeJoinError),
     #[error("unbond tx process failed: {0}")]
     Unbond(#[from] UnbondError),
+    #[error("rebond process failed: {0}")]
+    Rebond(#[from] RebondError),
+}
+
+impl PublicTxError {
+    /// Stable error code, the codes of the sub-errors are offset by the kind of tx:
+    /// - 1-5: common checks of public tx
+    /// - 100+: `UnjailError`
+    /// - 200+: `NodeJoinError`
+    /// - 300+: `UnbondError`
+    /// - 400+: `RebondError`
+    ///
+    /// Existing codes must not be changed, new variants should be assigned new numbers.
+    pub fn code(&self) -> u32 {
+        match self {
+            PublicTxError::WrongChainHexId => 1,
+            PublicTxError::UnsupportedVersion => 2,
+            PublicTxError::StakingWitnessVerify(_) => 3,
+            PublicTxError::StakingWitnessNotMatch => 4,
+            PublicTxError::IncorrectNonce { .. } => 5,
+            PublicTxError::Unjail(err) => 100 + err.code(),
+            PublicTxError::NodeJoin(err) => 200 + err.code(),
+            PublicTxError::Unbond(err) => 300 + err.code(),
+            PublicTxError::Rebond(err) => 400 + err.code(),
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -39,32 +67,96 @@ pub enum UnjailError {
     NotJailed,
     #[error("the jail duration is not reached yet")]
     JailTimeNotExpired,
+    #[error("the staking address is jailed permanently")]
+    PermanentlyJailed,
+    #[error("the staking address is not a validator")]
+    NotAValidator,
+}
+
+impl UnjailError {
+    /// Stable error code, see `PublicTxError::code`
+    pub fn code(&self) -> u32 {
+        match self {
+            UnjailError::NotJailed => 1,
+            UnjailError::JailTimeNotExpired => 2,
+            UnjailError::PermanentlyJailed => 3,
+            UnjailError::NotAValidator => 4,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum NodeJoinError {
+    #[error("wrong chain hex id in attributes")]
+    WrongChainHexId,
+    #[error("keypackage is already registered")]
+    KeyPackageAlreadyRegistered,
     #[error("bonded coins not enough to become validator")]
     BondedNotEnough,
-    #[error("validator address already exists")]
-    DuplicateValidatorAddress,
+    #[error("validator address already used by {owner}")]
+    DuplicateValidatorAddress { owner: StakedStateAddress },
     #[error("the staking address is already active")]
     AlreadyJoined,
     #[error("the staking address is jailed")]
     IsJailed,
-    #[error("the used_validator_addresses queue is full")]
-    UsedValidatorAddrFull,
+    #[error(
+        "the validator set is full and the bonded coins don't exceed the lowest-staked validator"
+    )]
+    ValidatorSetFull,
+    #[error("the used_validator_addresses queue is full until {retry_after}")]
+    UsedValidatorAddrFull { retry_after: Timespec },
+    #[error("validator address index is out of sync with the staking state")]
+    IndexDesync,
+    #[error("the staking address is not a council node")]
+    NotCouncilNode,
+    #[error("the consensus pubkey of a council node can't be changed")]
+    ConsensusKeyImmutable,
+    #[error("self-bonded coins are below the floor recorded at join")]
+    SelfBondedNotEnough,
+    #[error("the enclave isv_svn {got} is older than the most recent one {floor}")]
+    StaleEnclaveVersion { got: u16, floor: u16 },
     #[error("key package decode failed")]
     KeyPackageDecodeError,
+    #[error("the keypackage payload is empty")]
+    EmptyKeyPackage,
     #[error("invalid key package: {0}")]
     KeyPackageVerifyError(#[from] keypackage::Error),
-    #[error("FIXME: WIP -- community node not yet supported")]
-    WIPNotValidator,
+}
+
+impl NodeJoinError {
+    /// Stable error code, see `PublicTxError::code`
+    pub fn code(&self) -> u32 {
+        match self {
+            NodeJoinError::WrongChainHexId => 1,
+            NodeJoinError::KeyPackageAlreadyRegistered => 2,
+            NodeJoinError::BondedNotEnough => 3,
+            NodeJoinError::DuplicateValidatorAddress { .. } => 4,
+            NodeJoinError::AlreadyJoined => 5,
+            NodeJoinError::IsJailed => 6,
+            NodeJoinError::ValidatorSetFull => 7,
+            NodeJoinError::UsedValidatorAddrFull { .. } => 8,
+            NodeJoinError::IndexDesync => 9,
+            NodeJoinError::NotCouncilNode => 10,
+            NodeJoinError::ConsensusKeyImmutable => 11,
+            NodeJoinError::KeyPackageDecodeError => 12,
+            NodeJoinError::KeyPackageVerifyError(_) => 13,
+            NodeJoinError::SelfBondedNotEnough => 14,
+            NodeJoinError::StaleEnclaveVersion { .. } => 15,
+            NodeJoinError::EmptyKeyPackage => 16,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum WithdrawError {
-    #[error("unbonded amount {0} not equal to desired amount: {0}")]
+    #[error("unbonded amount {0} not equal to desired amount: {1}")]
     UnbondedSanityCheck(Coin, Coin),
+    #[error("unbonded amount {0} less than desired amount: {1}")]
+    UnbondedNotEnough(Coin, Coin),
+    #[error("withdrawn amount {0} not equal to the output value: {1}")]
+    OutputValueMismatch(Coin, Coin),
+    #[error("the value of withdraw is zero")]
+    ZeroValue,
     #[error("still in unbonding period")]
     InUnbondingPeriod,
     #[error("the staking address is jailed")]
@@ -77,10 +169,64 @@ pub enum UnbondError {
     NonceNotMatch,
     #[error("coin error in unbond tx: {0}")]
     CoinError(#[from] CoinError),
+    #[error("bonded amount {available} less than requested amount (value + fee): {requested}")]
+    InsufficientBonded { available: Coin, requested: Coin },
     #[error("the staking address is jailed")]
     IsJailed,
     #[error("the value of tx is zero")]
     ZeroValue,
+    #[error("the value of tx is below the minimal unbond amount {min}")]
+    BelowMinimum { min: Coin },
+    #[error("the unbonded coins overflow after adding the value of tx")]
+    UnbondedOverflow,
+    #[error("the value of tx plus the fee overflows")]
+    FeeOverflow,
+    #[error("the bonded coins underflow after subtracting the value of tx and the fee")]
+    BondedUnderflow,
+    #[error("the bonded coins are locked until {until}")]
+    BondLocked { until: Timespec },
+}
+
+impl UnbondError {
+    /// Stable error code, see `PublicTxError::code`
+    pub fn code(&self) -> u32 {
+        match self {
+            UnbondError::NonceNotMatch => 1,
+            UnbondError::CoinError(_) => 2,
+            UnbondError::InsufficientBonded { .. } => 3,
+            UnbondError::IsJailed => 4,
+            UnbondError::ZeroValue => 5,
+            UnbondError::BelowMinimum { .. } => 6,
+            UnbondError::UnbondedOverflow => 7,
+            UnbondError::FeeOverflow => 8,
+            UnbondError::BondedUnderflow => 9,
+            UnbondError::BondLocked { .. } => 10,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RebondError {
+    #[error("coin error in rebond: {0}")]
+    CoinError(#[from] CoinError),
+    #[error("the staking address is jailed")]
+    IsJailed,
+    #[error("the value to rebond is zero")]
+    ZeroValue,
+    #[error("unbonded amount {0} less than desired amount: {1}")]
+    UnbondedNotEnough(Coin, Coin),
+}
+
+impl RebondError {
+    /// Stable error code, see `PublicTxError::code`
+    pub fn code(&self) -> u32 {
+        match self {
+            RebondError::CoinError(_) => 1,
+            RebondError::IsJailed => 2,
+            RebondError::ZeroValue => 3,
+            RebondError::UnbondedNotEnough(..) => 4,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -89,4 +235,100 @@ pub enum DepositError {
     CoinError(#[from] CoinError),
     #[error("the staking address is jailed")]
     IsJailed,
+    #[error("the staking account doesn't exist")]
+    AccountNotFound,
+    #[error("deposit to {address} in batch failed: {source}")]
+    InBatch {
+        address: StakedStateAddress,
+        source: Box<DepositError>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn check_public_tx_error_codes() {
+        let coin = Coin::zero();
+        let errors = vec![
+            (PublicTxError::WrongChainHexId, 1),
+            (PublicTxError::UnsupportedVersion, 2),
+            (
+                PublicTxError::StakingWitnessVerify(secp256k1::Error::InvalidSignature),
+                3,
+            ),
+            (PublicTxError::StakingWitnessNotMatch, 4),
+            (
+                PublicTxError::IncorrectNonce {
+                    expected: 1,
+                    got: 0,
+                },
+                5,
+            ),
+            (UnjailError::NotJailed.into(), 101),
+            (UnjailError::JailTimeNotExpired.into(), 102),
+            (UnjailError::PermanentlyJailed.into(), 103),
+            (UnjailError::NotAValidator.into(), 104),
+            (NodeJoinError::WrongChainHexId.into(), 201),
+            (NodeJoinError::KeyPackageAlreadyRegistered.into(), 202),
+            (NodeJoinError::BondedNotEnough.into(), 203),
+            (
+                NodeJoinError::DuplicateValidatorAddress {
+                    owner: StakedStateAddress::BasicRedeem(Default::default()),
+                }
+                .into(),
+                204,
+            ),
+            (NodeJoinError::AlreadyJoined.into(), 205),
+            (NodeJoinError::IsJailed.into(), 206),
+            (NodeJoinError::ValidatorSetFull.into(), 207),
+            (
+                NodeJoinError::UsedValidatorAddrFull { retry_after: 1 }.into(),
+                208,
+            ),
+            (NodeJoinError::IndexDesync.into(), 209),
+            (NodeJoinError::NotCouncilNode.into(), 210),
+            (NodeJoinError::ConsensusKeyImmutable.into(), 211),
+            (NodeJoinError::KeyPackageDecodeError.into(), 212),
+            (
+                NodeJoinError::KeyPackageVerifyError(keypackage::Error::InvalidCredential).into(),
+                213,
+            ),
+            (NodeJoinError::SelfBondedNotEnough.into(), 214),
+            (
+                NodeJoinError::StaleEnclaveVersion { got: 1, floor: 2 }.into(),
+                215,
+            ),
+            (NodeJoinError::EmptyKeyPackage.into(), 216),
+            (UnbondError::NonceNotMatch.into(), 301),
+            (UnbondError::CoinError(CoinError::Negative).into(), 302),
+            (
+                UnbondError::InsufficientBonded {
+                    available: coin,
+                    requested: coin,
+                }
+                .into(),
+                303,
+            ),
+            (UnbondError::IsJailed.into(), 304),
+            (UnbondError::ZeroValue.into(), 305),
+            (UnbondError::BelowMinimum { min: coin }.into(), 306),
+            (UnbondError::UnbondedOverflow.into(), 307),
+            (UnbondError::FeeOverflow.into(), 308),
+            (UnbondError::BondedUnderflow.into(), 309),
+            (UnbondError::BondLocked { until: 1 }.into(), 310),
+            (RebondError::CoinError(CoinError::Negative).into(), 401),
+            (RebondError::IsJailed.into(), 402),
+            (RebondError::ZeroValue.into(), 403),
+            (RebondError::UnbondedNotEnough(coin, coin).into(), 404),
+        ];
+        let mut codes = HashSet::new();
+        for (err, code) in errors.iter() {
+            assert_eq!(err.code(), *code, "{}", err);
+            assert!(codes.insert(err.code()));
+        }
+    }
 }
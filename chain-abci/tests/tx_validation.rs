@@ -11,17 +11,17 @@ use chain_abci::storage::{
 use chain_abci::tx_error::{NodeJoinError, PublicTxError, TxError, UnbondError, UnjailError};
 use chain_core::common::{MerkleTree, Timespec};
 use chain_core::init::address::RedeemAddress;
-use chain_core::init::coin::{Coin, CoinError};
+use chain_core::init::coin::Coin;
 use chain_core::state::account::StakedState;
 use chain_core::state::account::StakedStateAddress;
 use chain_core::state::account::StakedStateOpAttributes;
 use chain_core::state::account::{
-    DepositBondTx, NodeMetadata, StakedStateOpWitness, UnbondTx, UnjailTx, Validator,
-    WithdrawUnbondedTx,
+    DepositBondTx, JailReason, NodeMetadata, NodeState, StakedStateOpWitness, UnbondTx, UnjailTx,
+    Validator, WithdrawUnbondedTx,
 };
 use chain_core::state::tendermint::BlockHeight;
 use chain_core::state::tendermint::TendermintValidatorPubKey;
-use chain_core::state::validator::NodeJoinRequestTx;
+use chain_core::state::validator::{EditValidatorTx, NodeJoinRequestTx};
 use chain_core::tx::data::{
     address::ExtendedAddr,
     attribute::TxAttributes,
@@ -321,7 +321,13 @@ fn test_account_unbond_verify_fail() {
             0,
             &create_storage(),
         );
-        expect_error_public(&result, PublicTxError::IncorrectNonce);
+        expect_error_public(
+            &result,
+            PublicTxError::IncorrectNonce {
+                expected: 0,
+                got: 1,
+            },
+        );
     }
     // AccountIncorrectNonce
     {
@@ -332,7 +338,13 @@ fn test_account_unbond_verify_fail() {
             get_account_op_witness(Secp256k1::new(), &tx.id(), &secret_key),
         );
         let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
-        expect_error_public(&result, PublicTxError::IncorrectNonce);
+        expect_error_public(
+            &result,
+            PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 0,
+            },
+        );
     }
     // ZeroCoin
     {
@@ -345,7 +357,7 @@ fn test_account_unbond_verify_fail() {
         let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
         expect_error_unbond(&result, UnbondError::ZeroValue);
     }
-    // InputOutputDoNotMatch
+    // InsufficientBonded
     {
         let mut tx = tx;
         tx.value = (tx.value + Coin::one()).unwrap();
@@ -354,7 +366,13 @@ fn test_account_unbond_verify_fail() {
             get_account_op_witness(Secp256k1::new(), &tx.id(), &secret_key),
         );
         let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
-        expect_error_unbond(&result, UnbondError::CoinError(CoinError::Negative));
+        expect_error_unbond(
+            &result,
+            UnbondError::InsufficientBonded {
+                available: Coin::one(),
+                requested: (tx.value + extra_info.min_fee_computed.to_coin()).unwrap(),
+            },
+        );
     }
 }
 
@@ -1134,6 +1152,17 @@ fn prepare_jailed_accounts() -> (
     SecretKey,
     RedeemAddress,
     MerkleTree<RawXOnlyPubkey>,
+) {
+    prepare_jailed_accounts_with_delegate(None)
+}
+
+fn prepare_jailed_accounts_with_delegate(
+    authorized_delegate: Option<StakedStateAddress>,
+) -> (
+    Storage,
+    SecretKey,
+    RedeemAddress,
+    MerkleTree<RawXOnlyPubkey>,
 ) {
     let mut storage = create_storage();
 
@@ -1153,6 +1182,12 @@ fn prepare_jailed_accounts() -> (
         Some(Validator {
             council_node: mock_council_node_meta(TendermintValidatorPubKey::Ed25519([0xcd; 32])),
             jailed_until: Some(100),
+            jail_reason: Some(JailReason::NonLive),
+            consecutive_jail_count: 1,
+            last_jailed_until: Some(100),
+            last_unjailed_at: None,
+            authorized_delegate,
+            min_self_bonded: Coin::zero(),
             inactive_time: Some(0),
             inactive_block: Some(BlockHeight::genesis()),
             used_validator_addresses: vec![],
@@ -1287,7 +1322,10 @@ fn check_unjail_transaction() {
 
     expect_error_public(
         &verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage),
-        PublicTxError::IncorrectNonce,
+        PublicTxError::IncorrectNonce {
+            expected: 1,
+            got: 0,
+        },
     );
 
     // Before `jailed_until`
@@ -1326,6 +1364,44 @@ fn check_unjail_transaction() {
     assert!(!new_account.unwrap().is_jailed());
 }
 
+#[test]
+fn check_unjail_transaction_by_authorized_delegate() {
+    let secp = Secp256k1::new();
+    let delegate_key = SecretKey::from_slice(&[0xce; 32]).expect("32 bytes, within curve order");
+    let delegate = RedeemAddress::from(&PublicKey::from_secret_key(&secp, &delegate_key));
+    let (storage, _secret_key, address, _merkle_tree) =
+        prepare_jailed_accounts_with_delegate(Some(delegate.into()));
+    let other_key = SecretKey::from_slice(&[0xcf; 32]).expect("32 bytes, within curve order");
+
+    // Signed by neither the staking address nor the delegate
+
+    let txaux = prepare_unjail_transaction(&other_key, StakedStateAddress::BasicRedeem(address), 1);
+    let mut extra_info = get_chain_info_pub(&txaux);
+    extra_info.block_time = 101;
+
+    expect_error_public(
+        &verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage),
+        PublicTxError::StakingWitnessNotMatch,
+    );
+
+    // Signed by the delegate
+
+    let txaux =
+        prepare_unjail_transaction(&delegate_key, StakedStateAddress::BasicRedeem(address), 1);
+
+    let (fee, new_account) =
+        verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage)
+            .expect("Verification of unjail transaction signed by the delegate failed");
+
+    assert_eq!(Fee::new(Coin::zero()), fee);
+    let new_account = new_account.unwrap();
+    assert_eq!(
+        new_account.address,
+        StakedStateAddress::BasicRedeem(address)
+    );
+    assert!(!new_account.is_jailed());
+}
+
 fn prepare_nodejoin_transaction(
     secret_key: &SecretKey,
     address: StakedStateAddress,
@@ -1426,7 +1502,13 @@ fn test_nodejoin_fail() {
             0,
             &create_storage(),
         );
-        expect_error_public(&result, PublicTxError::IncorrectNonce);
+        expect_error_public(
+            &result,
+            PublicTxError::IncorrectNonce {
+                expected: 0,
+                got: 1,
+            },
+        );
     }
     // AccountIncorrectNonce
     {
@@ -1437,7 +1519,13 @@ fn test_nodejoin_fail() {
             get_account_op_witness(Secp256k1::new(), &tx.id(), &secret_key),
         );
         let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
-        expect_error_public(&result, PublicTxError::IncorrectNonce);
+        expect_error_public(
+            &result,
+            PublicTxError::IncorrectNonce {
+                expected: 1,
+                got: 0,
+            },
+        );
     }
     // MismatchAccountAddress
     {
@@ -1464,3 +1552,81 @@ fn test_nodejoin_fail() {
         expect_error_joinnode(&result, NodeJoinError::AlreadyJoined);
     }
 }
+
+fn prepare_edit_validator_transaction(
+    secret_key: &SecretKey,
+    address: StakedStateAddress,
+    consensus_pubkey: TendermintValidatorPubKey,
+) -> (TxPublicAux, EditValidatorTx) {
+    let secp = Secp256k1::new();
+
+    let tx = EditValidatorTx {
+        nonce: 1,
+        address,
+        attributes: StakedStateOpAttributes::new(DEFAULT_CHAIN_ID),
+        name: "edited".to_owned(),
+        security_contact: Some("security@example.com".to_owned()),
+        consensus_pubkey,
+        authorized_delegate: Some(StakedStateAddress::from(RedeemAddress::from([1u8; 20]))),
+    };
+    let witness = get_account_op_witness(secp, &tx.id(), &secret_key);
+
+    (TxPublicAux::EditValidatorTx(tx.clone(), witness), tx)
+}
+
+#[test]
+fn check_edit_validator_transaction() {
+    let (_, _, addr, secret_key, storage) = prepare_valid_nodejoin_tx(true);
+    let (txaux, tx) = prepare_edit_validator_transaction(
+        &secret_key,
+        addr,
+        TendermintValidatorPubKey::Ed25519([1u8; 32]),
+    );
+    let extra_info = get_chain_info_pub(&txaux);
+    // MismatchAccountAddress
+    {
+        let other_key = SecretKey::from_slice(&[0xce; 32]).expect("32 bytes, within curve order");
+        let txaux = TxPublicAux::EditValidatorTx(
+            tx.clone(),
+            get_account_op_witness(Secp256k1::new(), &tx.id(), &other_key),
+        );
+        let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
+        expect_error_public(&result, PublicTxError::StakingWitnessNotMatch);
+    }
+    // ConsensusKeyImmutable
+    {
+        let (txaux, _) = prepare_edit_validator_transaction(
+            &secret_key,
+            addr,
+            TendermintValidatorPubKey::Ed25519([2u8; 32]),
+        );
+        let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
+        expect_error_joinnode(&result, NodeJoinError::ConsensusKeyImmutable);
+    }
+    // NotCouncilNode
+    {
+        let (_, _, addr, secret_key, storage) = prepare_valid_nodejoin_tx(false);
+        let (txaux, _) = prepare_edit_validator_transaction(
+            &secret_key,
+            addr,
+            TendermintValidatorPubKey::Ed25519([1u8; 32]),
+        );
+        let result = verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage);
+        expect_error_joinnode(&result, NodeJoinError::NotCouncilNode);
+    }
+
+    let (fee, new_account) =
+        verify_public_tx(&txaux, &extra_info, NodeInfoWrap::default(), 0, &storage)
+            .expect("Verification of edit validator transaction failed");
+
+    assert_eq!(Fee::new(Coin::zero()), fee);
+    let new_account = new_account.unwrap();
+    assert_eq!(new_account.nonce, 2);
+    match new_account.node_meta {
+        Some(NodeState::CouncilNode(val)) => {
+            assert_eq!(val.council_node.node_info.name, "edited");
+            assert_eq!(val.authorized_delegate, tx.authorized_delegate);
+        }
+        _ => panic!("council node expected"),
+    }
+}
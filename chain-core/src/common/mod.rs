@@ -1,3 +1,4 @@
+use core::time::Duration;
 use std::borrow::ToOwned;
 use std::fmt;
 use std::prelude::v1::{String, Vec};
@@ -22,9 +23,27 @@ pub fn hash256<D: Digest>(data: &[u8]) -> H256 {
     out
 }
 
+/// Writes bytes as lowercase hex, doesn't depend on `hex` (not available in SGX build)
+pub(crate) fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes.iter() {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
 /// Seconds since UNIX epoch
 pub type Timespec = u64;
 
+/// Time left until the unbonded coins are withdrawable at `unbonded_from`,
+/// `None` if already matured, i.e. `now >= unbonded_from`, same as the check of withdraw.
+pub fn time_remaining(unbonded_from: Timespec, now: Timespec) -> Option<Duration> {
+    if now >= unbonded_from {
+        None
+    } else {
+        Some(Duration::from_secs(unbonded_from - now))
+    }
+}
+
 /// 32-byte for keys or hashes etc.
 pub type H256 = [u8; HASH_SIZE_256];
 /// 33-byte for pubkeys etc.
@@ -136,3 +155,23 @@ impl TendermintEventKey {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_time_remaining() {
+        // pending
+        assert_eq!(time_remaining(100, 40), Some(Duration::from_secs(60)));
+        // exactly at the boundary it's withdrawable
+        assert_eq!(time_remaining(100, 100), None);
+        // matured
+        assert_eq!(time_remaining(100, 101), None);
+        // `unbonded_from` saturated in unbond
+        assert_eq!(
+            time_remaining(Timespec::max_value(), 0),
+            Some(Duration::from_secs(u64::max_value()))
+        );
+    }
+}
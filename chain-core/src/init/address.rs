@@ -24,11 +24,10 @@ use secp256k1::key::PublicKey;
 use serde::de::Error;
 #[cfg(not(feature = "mesalock_sgx"))]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-#[cfg(not(feature = "mesalock_sgx"))]
 use std::fmt;
 use tiny_keccak::{Hasher, Keccak};
 
-use crate::common::{H256, HASH_SIZE_256};
+use crate::common::{write_hex, H256, HASH_SIZE_256};
 #[cfg(not(feature = "mesalock_sgx"))]
 use crate::init::network::{get_bech32_human_part_from_network, Network};
 
@@ -276,10 +275,10 @@ impl FromStr for RedeemAddress {
     }
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
 impl fmt::Display for RedeemAddress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "0x{}", hex::encode(self.0))
+        write!(f, "0x")?;
+        write_hex(f, &self.0)
     }
 }
 
@@ -1,11 +1,14 @@
 mod address;
 mod op;
-use crate::common::{Timespec, HASH_SIZE_256};
-use crate::init::coin::Coin;
+use crate::common::{write_hex, Timespec, HASH_SIZE_256};
+use crate::init::coin::{sum_coins, Coin, CoinError, CoinResult};
+#[cfg(not(feature = "mesalock_sgx"))]
+use crate::init::config::SlashRatio;
 use crate::state::tendermint::{
     BlockHeight, TendermintValidatorAddress, TendermintValidatorPubKey,
 };
 pub use crate::state::validator::UnjailTx;
+use crate::tx::data::txid_hash;
 pub use address::StakedStateAddress;
 pub use op::data::attribute::StakedStateOpAttributes;
 pub use op::data::deposit::DepositBondTx;
@@ -16,7 +19,6 @@ use parity_scale_codec::{Decode, Encode, Error, Input, Output};
 #[cfg(not(feature = "mesalock_sgx"))]
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::From;
-#[cfg(not(feature = "mesalock_sgx"))]
 use std::fmt;
 use std::prelude::v1::Vec;
 use std::prelude::v1::{String, ToString};
@@ -78,14 +80,26 @@ pub struct NodeCommonInfo {
     pub confidential_init: ConfidentialInit,
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
+/// Number of keypackage hash bytes displayed
+const KEYPACKAGE_HASH_DISPLAY_LEN: usize = 8;
+
 impl fmt::Display for NodeCommonInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name)
+        write!(f, "{}, security contact: ", self.name)?;
+        match &self.security_contact {
+            Some(contact) => write!(f, "{}", contact)?,
+            None => write!(f, "none")?,
+        }
+        // the keypackage is large, only display the prefix of its hash
+        write!(f, ", keypackage: ")?;
+        write_hex(
+            f,
+            &txid_hash(&self.confidential_init.keypackage)[..KEYPACKAGE_HASH_DISPLAY_LEN],
+        )?;
+        write!(f, "...")
     }
 }
 
-// TODO: size hint
 impl Encode for NodeCommonInfo {
     fn encode_to<W: Output>(&self, dest: &mut W) {
         self.name.encode_to(dest);
@@ -98,9 +112,19 @@ impl Encode for NodeCommonInfo {
         };
         self.confidential_init.keypackage.encode_to(dest);
     }
+
+    fn size_hint(&self) -> usize {
+        self.name.size_hint()
+            + security_contact_size_hint(&self.security_contact)
+            + self.confidential_init.keypackage.size_hint()
+    }
+}
+
+fn security_contact_size_hint(security_contact: &NodeSecurityContact) -> usize {
+    1 + security_contact.as_ref().map_or(0, |c| c.size_hint())
 }
 
-fn decode_name_security_contact<I: Input>(
+pub(crate) fn decode_name_security_contact<I: Input>(
     input: &mut I,
 ) -> Result<(NodeName, NodeSecurityContact), Error> {
     let name_raw: Vec<u8> = Vec::decode(input)?;
@@ -121,7 +145,7 @@ fn decode_name_security_contact<I: Input>(
     Ok((name, security_contact))
 }
 
-const MAX_STRING_LEN: usize = 255;
+pub(crate) const MAX_STRING_LEN: usize = 255;
 
 impl Decode for NodeCommonInfo {
     fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
@@ -162,6 +186,13 @@ impl Encode for CouncilNodeMeta {
         self.consensus_pubkey.encode_to(dest);
         self.node_info.confidential_init.keypackage.encode_to(dest);
     }
+
+    fn size_hint(&self) -> usize {
+        self.node_info.name.size_hint()
+            + security_contact_size_hint(&self.node_info.security_contact)
+            + self.consensus_pubkey.size_hint()
+            + self.node_info.confidential_init.keypackage.size_hint()
+    }
 }
 
 impl Decode for CouncilNodeMeta {
@@ -191,7 +222,6 @@ pub enum NodeMetadata {
     CommunityNode(NodeCommonInfo),
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
 impl fmt::Display for NodeMetadata {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -201,7 +231,6 @@ impl fmt::Display for NodeMetadata {
     }
 }
 
-// TODO: size hint once MLS payloads are there
 impl Encode for NodeMetadata {
     fn encode_to<W: Output>(&self, dest: &mut W) {
         match self {
@@ -215,6 +244,13 @@ impl Encode for NodeMetadata {
             }
         }
     }
+
+    fn size_hint(&self) -> usize {
+        1 + match self {
+            NodeMetadata::CouncilNode(cm) => cm.size_hint(),
+            NodeMetadata::CommunityNode(cm) => cm.size_hint(),
+        }
+    }
 }
 
 impl Decode for NodeMetadata {
@@ -313,6 +349,25 @@ impl fmt::Display for PunishmentKind {
     }
 }
 
+/// Reason of validator being jailed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+#[cfg_attr(not(feature = "mesalock_sgx"), derive(Serialize, Deserialize))]
+pub enum JailReason {
+    /// liveness fault
+    NonLive,
+    /// byzantine fault, jailed permanently if the chain enables it
+    ByzantineFault,
+}
+
+impl From<PunishmentKind> for JailReason {
+    fn from(kind: PunishmentKind) -> Self {
+        match kind {
+            PunishmentKind::NonLive => JailReason::NonLive,
+            PunishmentKind::ByzantineFault => JailReason::ByzantineFault,
+        }
+    }
+}
+
 /// Details of a punishment for a staked state
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
 #[cfg_attr(not(feature = "mesalock_sgx"), derive(Serialize, Deserialize))]
@@ -325,10 +380,15 @@ pub struct SlashRecord {
     pub amount: Coin,
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
 impl fmt::Display for CouncilNodeMeta {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} -- {}", self.node_info, self.consensus_pubkey)
+        write!(
+            f,
+            "{} -- {} (validator address: {})",
+            self.node_info,
+            self.consensus_pubkey,
+            TendermintValidatorAddress::from(&self.consensus_pubkey)
+        )
     }
 }
 
@@ -342,12 +402,17 @@ impl fmt::Display for CouncilNodeMeta {
 ///
 /// Invariant 1.2:
 ///   `! (is_jailed() && is_active())`
+///
+/// Invariant 1.3:
+///   ```plain
+///   jailed_until.is_some() == jail_reason.is_some()
+///   ```
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
 #[cfg_attr(not(feature = "mesalock_sgx"), derive(Serialize, Deserialize))]
 pub struct Validator {
     /// council node metadata
     pub council_node: CouncilNodeMeta,
-    /// if jailed, it's specified until what block time
+    /// if jailed, it's specified until what block time, `Timespec::MAX` if jailed permanently
     pub jailed_until: Option<Timespec>,
 
     /// when it became inactive (from block time)
@@ -358,6 +423,19 @@ pub struct Validator {
     /// last N (10?) used consensus pubkeys/addresses
     #[cfg_attr(not(feature = "mesalock_sgx"), serde(skip))]
     pub used_validator_addresses: Vec<(TendermintValidatorAddress, Timespec)>,
+
+    /// if jailed, it's specified why
+    pub jail_reason: Option<JailReason>,
+    /// number of consecutive jailing, escalates the jail duration if enabled
+    pub consecutive_jail_count: u32,
+    /// the `jailed_until` of the last jailing, kept after unjailed to detect consecutive jailing
+    pub last_jailed_until: Option<Timespec>,
+    /// the block time of the last unjail, to detect the retried unjail tx
+    pub last_unjailed_at: Option<Timespec>,
+    /// another address allowed to sign `UnjailTx` on behalf of the validator
+    pub authorized_delegate: Option<StakedStateAddress>,
+    /// the floor of the operator's own bonded coins recorded at join, required when reactivating
+    pub min_self_bonded: Coin,
 }
 
 impl Validator {
@@ -369,6 +447,12 @@ impl Validator {
             inactive_time: None,
             inactive_block: None,
             used_validator_addresses: Vec::new(),
+            jail_reason: None,
+            consecutive_jail_count: 0,
+            last_jailed_until: None,
+            last_unjailed_at: None,
+            authorized_delegate: None,
+            min_self_bonded: Coin::zero(),
         }
     }
 
@@ -382,6 +466,11 @@ impl Validator {
         self.jailed_until.is_some()
     }
 
+    /// checks if jailed permanently, which can't be unjailed
+    pub fn is_permanently_jailed(&self) -> bool {
+        self.jailed_until == Some(Timespec::MAX)
+    }
+
     /// checks if active
     pub fn is_active(&self) -> bool {
         self.inactive_time.is_none()
@@ -398,19 +487,39 @@ impl Validator {
 
         // check: Invariant 1.2
         assert_eq!(self.is_jailed() && self.is_active(), false);
+
+        // check: Invariant 1.3
+        assert_eq!(self.jailed_until.is_some(), self.jail_reason.is_some());
     }
 
     /// updates this state to be "jailed"
+    ///
+    /// If `escalation_grace_period` is specified, jailing again within the grace period after the
+    /// last jail expired counts as consecutive, and the jail duration is multiplied by
+    /// the consecutive count; otherwise the count is reset to one.
     pub fn jail(
         &mut self,
         block_time: Timespec,
         block_height: BlockHeight,
         jail_duration: Timespec,
+        reason: JailReason,
+        escalation_grace_period: Option<Timespec>,
     ) -> Timespec {
         assert!(!self.is_jailed());
-        let jailed_until = block_time.saturating_add(jail_duration);
+        self.consecutive_jail_count = match (self.last_jailed_until, escalation_grace_period) {
+            (Some(last), Some(grace_period)) if block_time < last.saturating_add(grace_period) => {
+                self.consecutive_jail_count.saturating_add(1)
+            }
+            _ => 1,
+        };
+        // `Timespec::MAX` is reserved for the permanent jail
+        let jailed_until = block_time
+            .saturating_add(jail_duration.saturating_mul(self.consecutive_jail_count.into()))
+            .min(Timespec::MAX - 1);
 
         self.jailed_until = Some(jailed_until);
+        self.last_jailed_until = Some(jailed_until);
+        self.jail_reason = Some(reason);
         if self.is_active() {
             self.inactivate(block_time, block_height);
         }
@@ -418,6 +527,23 @@ impl Validator {
         jailed_until
     }
 
+    /// updates this state to be jailed permanently, which can't be unjailed
+    pub fn jail_permanently(
+        &mut self,
+        block_time: Timespec,
+        block_height: BlockHeight,
+        reason: JailReason,
+    ) -> Timespec {
+        assert!(!self.is_jailed());
+        self.jailed_until = Some(Timespec::MAX);
+        self.jail_reason = Some(reason);
+        if self.is_active() {
+            self.inactivate(block_time, block_height);
+        }
+
+        Timespec::MAX
+    }
+
     /// updates this state to be "inactive"
     pub fn inactivate(&mut self, block_time: Timespec, block_height: BlockHeight) {
         assert!(self.is_active());
@@ -425,10 +551,12 @@ impl Validator {
         self.inactive_block = Some(block_height);
     }
 
-    /// updates this state to be unjailed
-    pub fn unjail(&mut self) {
+    /// updates this state to be unjailed at `block_time`
+    pub fn unjail(&mut self, block_time: Timespec) {
         assert!(self.is_jailed());
         self.jailed_until = None;
+        self.jail_reason = None;
+        self.last_unjailed_at = Some(block_time);
     }
 }
 
@@ -454,6 +582,13 @@ pub enum NodeState {
 ///       }
 ///   }
 ///   ```
+///
+/// Invariant 4.3:
+///   - unbonding_queue is sorted by time, all the times are later than unbonded_from
+///   - no zero amount in unbonding_queue, and unbonded is not zero if unbonding_queue is not empty
+///
+/// Invariant 4.4:
+///   - bond_locks is sorted by time, no zero amount and no duplicated time in bond_locks
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
 #[cfg_attr(not(feature = "mesalock_sgx"), derive(Serialize, Deserialize))]
 pub struct StakedState {
@@ -471,6 +606,12 @@ pub struct StakedState {
     pub node_meta: Option<NodeState>,
     /// record the last slash only for query
     pub last_slash: Option<SlashRecord>,
+    /// coins unbonded later, which can be withdrawn at their own time after `unbonded_from`,
+    /// the next one is moved to `unbonded` when it's fully withdrawn
+    pub unbonding_queue: Vec<(Coin, Timespec)>,
+    /// the deposited amounts of the locked deposits, which can't be unbonded before their own time,
+    /// the expired ones are removed at the next locked deposit
+    pub bond_locks: Vec<(Coin, Timespec)>,
 }
 
 /// the tree used in StakedState storage db has a hardcoded 32-byte keys,
@@ -507,6 +648,8 @@ impl StakedState {
             address,
             node_meta: validator.map(NodeState::CouncilNode),
             last_slash: None,
+            unbonding_queue: Vec::new(),
+            bond_locks: Vec::new(),
         }
     }
 
@@ -520,6 +663,8 @@ impl StakedState {
             unbonded_from: 0,
             node_meta: None,
             last_slash: None,
+            unbonding_queue: Vec::new(),
+            bond_locks: Vec::new(),
         }
     }
 
@@ -564,11 +709,208 @@ impl StakedState {
         }
     }
 
+    /// Return is jailed permanently, non validator default to false.
+    pub fn is_permanently_jailed(&self) -> bool {
+        if let Some(NodeState::CouncilNode(v)) = &self.node_meta {
+            v.is_permanently_jailed()
+        } else {
+            false
+        }
+    }
+
+    /// Total unbonded coins, including the ones in the unbonding queue
+    pub fn total_unbonded(&self) -> CoinResult {
+        self.unbonded + sum_coins(self.unbonding_queue.iter().map(|(amount, _)| *amount))?
+    }
+
+    /// Add unbonded coins which can be withdrawn at `unbonded_from`,
+    /// the coins unbonded before keep their own time.
+    pub fn add_unbonded(&mut self, amount: Coin, unbonded_from: Timespec) -> Result<(), CoinError> {
+        // check overflow before modifying
+        (self.total_unbonded()? + amount)?;
+        if amount == Coin::zero() {
+            return Ok(());
+        }
+        if self.unbonded == Coin::zero() {
+            self.unbonded = amount;
+            self.unbonded_from = unbonded_from;
+            return Ok(());
+        }
+        let mut batches = Vec::with_capacity(self.unbonding_queue.len() + 2);
+        batches.push((self.unbonded, self.unbonded_from));
+        batches.append(&mut self.unbonding_queue);
+        match batches.binary_search_by_key(&unbonded_from, |(_, time)| *time) {
+            // no panic: the sum is checked above
+            Ok(i) => batches[i].0 = (batches[i].0 + amount).unwrap(),
+            Err(i) => batches.insert(i, (amount, unbonded_from)),
+        }
+        let (unbonded, unbonded_from) = batches.remove(0);
+        self.unbonded = unbonded;
+        self.unbonded_from = unbonded_from;
+        self.unbonding_queue = batches;
+        Ok(())
+    }
+
+    /// Bonded coins locked from unbonding at `block_time`, it can be more than `bonded` after a slash.
+    pub fn locked_bonded(&self, block_time: Timespec) -> CoinResult {
+        sum_coins(
+            self.bond_locks
+                .iter()
+                .filter(|(_, until)| block_time < *until)
+                .map(|(amount, _)| *amount),
+        )
+    }
+
+    /// Lock `amount` of the bonded coins from unbonding until `locked_until`,
+    /// the locks expired at `block_time` are removed.
+    pub fn add_bond_lock(
+        &mut self,
+        amount: Coin,
+        locked_until: Timespec,
+        block_time: Timespec,
+    ) -> Result<(), CoinError> {
+        // check overflow before modifying
+        (self.locked_bonded(block_time)? + amount)?;
+        self.bond_locks.retain(|(_, until)| block_time < *until);
+        if amount == Coin::zero() || locked_until <= block_time {
+            return Ok(());
+        }
+        match self
+            .bond_locks
+            .binary_search_by_key(&locked_until, |(_, until)| *until)
+        {
+            // no panic: the sum is checked above
+            Ok(i) => self.bond_locks[i].0 = (self.bond_locks[i].0 + amount).unwrap(),
+            Err(i) => self.bond_locks.insert(i, (amount, locked_until)),
+        }
+        Ok(())
+    }
+
+    /// The earliest time when `amount` of the bonded coins can be unbonded,
+    /// `None` if they are not locked at `block_time`.
+    pub fn bond_unlocked_at(&self, amount: Coin, block_time: Timespec) -> Option<Timespec> {
+        let unlocked = |locked: Coin| (self.bonded - locked).unwrap_or_else(|_| Coin::zero());
+        let mut locked = self
+            .locked_bonded(block_time)
+            .unwrap_or_else(|_| Coin::max());
+        if unlocked(locked) >= amount {
+            return None;
+        }
+        let locks = self
+            .bond_locks
+            .iter()
+            .filter(|(_, until)| block_time < *until);
+        for (lock, until) in locks {
+            locked = (locked - *lock).unwrap_or_else(|_| Coin::zero());
+            if unlocked(locked) >= amount {
+                return Some(*until);
+            }
+        }
+        // unreachable unless `amount` is more than `bonded`, it's not the locks which prevent it
+        None
+    }
+
+    /// Remove unbonded coins, the latest unbonded ones are removed first.
+    pub fn sub_unbonded_latest(&mut self, mut amount: Coin) -> Result<(), CoinError> {
+        if amount > self.total_unbonded()? {
+            return Err(CoinError::Negative);
+        }
+        while let Some((last, _)) = self.unbonding_queue.last_mut() {
+            if amount < *last {
+                // no panic: checked above
+                *last = (*last - amount).unwrap();
+                return Ok(());
+            }
+            amount = (amount - *last).unwrap();
+            self.unbonding_queue.pop();
+        }
+        // no panic: checked at the beginning
+        self.unbonded = (self.unbonded - amount).unwrap();
+        self.advance_unbonding_queue();
+        Ok(())
+    }
+
+    /// Remove unbonded coins which are withdrawable at `unbonded_from`,
+    /// the next ones in the queue become withdrawable if it's fully withdrawn.
+    pub fn sub_unbonded(&mut self, amount: Coin) -> Result<(), CoinError> {
+        self.unbonded = (self.unbonded - amount)?;
+        self.advance_unbonding_queue();
+        Ok(())
+    }
+
+    /// Unbonded coins withdrawable at `block_time`, including the matured ones in the queue.
+    pub fn matured_unbonded(&self, block_time: Timespec) -> CoinResult {
+        if block_time < self.unbonded_from {
+            return Ok(Coin::zero());
+        }
+        let matured = self
+            .unbonding_queue
+            .iter()
+            .take_while(|(_, time)| *time <= block_time)
+            .map(|(amount, _)| *amount);
+        self.unbonded + sum_coins(matured)?
+    }
+
+    /// Remove all the unbonded coins withdrawable at `block_time`, returns the removed amount,
+    /// the coins in the queue which are not matured yet are kept.
+    pub fn sub_matured_unbonded(&mut self, block_time: Timespec) -> CoinResult {
+        let amount = self.matured_unbonded(block_time)?;
+        if amount > Coin::zero() {
+            let matured = self
+                .unbonding_queue
+                .iter()
+                .take_while(|(_, time)| *time <= block_time)
+                .count();
+            self.unbonded = Coin::zero();
+            self.unbonding_queue.drain(..matured);
+            self.advance_unbonding_queue();
+        }
+        Ok(amount)
+    }
+
+    /// Slash all the unbonded coins by `ratio`, returns the slashed amount.
+    #[cfg(not(feature = "mesalock_sgx"))]
+    pub fn slash_unbonded(&mut self, ratio: SlashRatio) -> Coin {
+        let mut slashed = self.unbonded * ratio;
+        // no panic: SlashRatio invariant(<= 1.0)
+        self.unbonded = (self.unbonded - slashed).unwrap();
+        for (amount, _) in self.unbonding_queue.iter_mut() {
+            let amount_slashed = *amount * ratio;
+            // no panic: SlashRatio invariant(<= 1.0)
+            *amount = (*amount - amount_slashed).unwrap();
+            // no panic: Invariant 4.1
+            slashed = (slashed + amount_slashed).unwrap();
+        }
+        self.unbonding_queue
+            .retain(|(amount, _)| *amount > Coin::zero());
+        self.advance_unbonding_queue();
+        slashed
+    }
+
+    /// Move the next coins in the queue to `unbonded` if it's empty
+    fn advance_unbonding_queue(&mut self) {
+        if self.unbonded == Coin::zero() && !self.unbonding_queue.is_empty() {
+            let (unbonded, unbonded_from) = self.unbonding_queue.remove(0);
+            self.unbonded = unbonded;
+            self.unbonded_from = unbonded_from;
+        }
+    }
+
     /// extra dynamic assertions
     #[cfg(debug_assertions)]
     pub fn check_invariants(&self, minimal_required_staking: Coin) {
         // check: Invariant 4.1
-        (self.bonded + self.unbonded).unwrap();
+        (self.bonded + self.total_unbonded().unwrap()).unwrap();
+
+        // check: Invariant 4.3
+        if !self.unbonding_queue.is_empty() {
+            assert!(self.unbonded > Coin::zero());
+        }
+        let mut last_time = self.unbonded_from;
+        for (amount, time) in self.unbonding_queue.iter() {
+            assert!(*amount > Coin::zero() && *time > last_time);
+            last_time = *time;
+        }
 
         // check: Invariant 4.2
         if let Some(NodeState::CouncilNode(val)) = &self.node_meta {
@@ -600,6 +942,7 @@ pub enum StakedStateDestination {
 mod test {
 
     use super::*;
+    use crate::init::address::RedeemAddress;
     use quickcheck::quickcheck;
     use quickcheck::Arbitrary;
     use quickcheck::Gen;
@@ -637,6 +980,74 @@ mod test {
         }
     }
 
+    #[test]
+    fn check_bond_locks() {
+        let mut staking =
+            StakedState::default(StakedStateAddress::BasicRedeem(RedeemAddress([0xaa; 20])));
+        let coin = |value| Coin::new(value).unwrap();
+        staking.bonded = coin(10);
+        staking.add_bond_lock(coin(2), 20, 0).unwrap();
+        staking.add_bond_lock(coin(3), 10, 0).unwrap();
+        staking.add_bond_lock(coin(1), 20, 0).unwrap();
+        // expired already
+        staking.add_bond_lock(coin(1), 5, 5).unwrap();
+        assert_eq!(staking.bond_locks, vec![(coin(3), 10), (coin(3), 20)]);
+        assert_eq!(staking.locked_bonded(5).unwrap(), coin(6));
+        assert_eq!(staking.locked_bonded(10).unwrap(), coin(3));
+
+        assert_eq!(staking.bond_unlocked_at(coin(4), 5), None);
+        assert_eq!(staking.bond_unlocked_at(coin(5), 5), Some(10));
+        assert_eq!(staking.bond_unlocked_at(coin(8), 5), Some(20));
+        assert_eq!(staking.bond_unlocked_at(coin(8), 20), None);
+        // more locked than bonded after a slash
+        staking.bonded = coin(4);
+        assert_eq!(staking.bond_unlocked_at(coin(1), 5), Some(10));
+
+        // the expired ones are removed
+        staking.add_bond_lock(coin(1), 30, 10).unwrap();
+        assert_eq!(staking.bond_locks, vec![(coin(3), 20), (coin(1), 30)]);
+        assert!(staking.add_bond_lock(Coin::max(), 40, 10).is_err());
+        assert_eq!(staking.bond_locks, vec![(coin(3), 20), (coin(1), 30)]);
+    }
+
+    #[test]
+    fn check_unbonding_queue() {
+        let mut staking =
+            StakedState::default(StakedStateAddress::BasicRedeem(RedeemAddress([0xaa; 20])));
+        let coin = |value| Coin::new(value).unwrap();
+        staking.add_unbonded(coin(1), 10).unwrap();
+        staking.add_unbonded(coin(2), 20).unwrap();
+        staking.add_unbonded(coin(3), 20).unwrap();
+        // unbonded immediately
+        staking.add_unbonded(coin(4), 5).unwrap();
+        assert_eq!((staking.unbonded, staking.unbonded_from), (coin(4), 5));
+        assert_eq!(staking.unbonding_queue, vec![(coin(1), 10), (coin(5), 20)]);
+        assert_eq!(staking.total_unbonded().unwrap(), coin(10));
+        assert!(staking.add_unbonded(Coin::max(), 30).is_err());
+
+        staking.sub_unbonded_latest(coin(6)).unwrap();
+        assert_eq!((staking.unbonded, staking.unbonded_from), (coin(4), 5));
+        assert!(staking.unbonding_queue.is_empty());
+        assert!(staking.sub_unbonded_latest(coin(5)).is_err());
+
+        staking.add_unbonded(coin(1), 10).unwrap();
+        staking.sub_unbonded(coin(4)).unwrap();
+        assert_eq!((staking.unbonded, staking.unbonded_from), (coin(1), 10));
+        assert!(staking.unbonding_queue.is_empty());
+
+        // the matured batches are removed together
+        staking.add_unbonded(coin(2), 20).unwrap();
+        staking.add_unbonded(coin(3), 30).unwrap();
+        assert_eq!(staking.matured_unbonded(9).unwrap(), Coin::zero());
+        assert_eq!(staking.matured_unbonded(20).unwrap(), coin(3));
+        assert_eq!(staking.sub_matured_unbonded(25).unwrap(), coin(3));
+        assert_eq!((staking.unbonded, staking.unbonded_from), (coin(3), 30));
+        assert!(staking.unbonding_queue.is_empty());
+        assert_eq!(staking.sub_matured_unbonded(25).unwrap(), Coin::zero());
+        assert_eq!(staking.sub_matured_unbonded(30).unwrap(), coin(3));
+        assert_eq!(staking.total_unbonded().unwrap(), Coin::zero());
+    }
+
     quickcheck! {
         // tests if decode(encode(x)) == x
         fn prop_encode_decode_council_node(council_node: CouncilNodeMeta) -> bool {
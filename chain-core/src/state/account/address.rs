@@ -7,7 +7,6 @@ use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::From;
 #[cfg(not(feature = "mesalock_sgx"))]
 use std::convert::TryFrom;
-#[cfg(not(feature = "mesalock_sgx"))]
 use std::fmt;
 #[cfg(not(feature = "mesalock_sgx"))]
 use std::str::FromStr;
@@ -104,7 +103,6 @@ impl From<RedeemAddress> for StakedStateAddress {
     }
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
 impl fmt::Display for StakedStateAddress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
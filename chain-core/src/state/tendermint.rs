@@ -1,3 +1,4 @@
+use crate::common::write_hex;
 use crate::init::coin::Coin;
 use crate::init::MAX_COIN_DECIMALS;
 use parity_scale_codec::{Decode, Encode, EncodeLike, Error, Input, Output};
@@ -223,11 +224,10 @@ where
         .map_err(|e| D::Error::custom(format!("{}", e)))
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
 impl fmt::Display for TendermintValidatorPubKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TendermintValidatorPubKey::Ed25519(key) => write!(f, "{}", hex::encode(key)),
+            TendermintValidatorPubKey::Ed25519(key) => write_hex(f, key),
         }
     }
 }
@@ -326,10 +326,9 @@ impl<'de> Deserialize<'de> for TendermintValidatorAddress {
     }
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
 impl fmt::Display for TendermintValidatorAddress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", hex::encode(&self.0))
+        write_hex(f, &self.0)
     }
 }
 
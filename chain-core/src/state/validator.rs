@@ -1,5 +1,9 @@
+mod edit;
 mod nodejoin;
 mod unjail;
 
+pub use edit::EditValidatorTx;
 pub use nodejoin::NodeJoinRequestTx;
+#[cfg(not(feature = "mesalock_sgx"))]
+pub use nodejoin::NodeJoinWitnessError;
 pub use unjail::UnjailTx;
@@ -0,0 +1,122 @@
+use crate::state::account::{
+    decode_name_security_contact, NodeName, NodeSecurityContact, Nonce, StakedStateAddress,
+    StakedStateOpAttributes,
+};
+use crate::state::tendermint::TendermintValidatorPubKey;
+use crate::tx::TransactionId;
+use parity_scale_codec::{Decode, Encode, Error, Input, Output};
+#[cfg(not(feature = "mesalock_sgx"))]
+use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "mesalock_sgx"))]
+use std::fmt;
+
+/// Updates the informational metadata (name, security contact) and the authorized delegate of a
+/// council node, it's allowed while the validator is active.
+///
+/// tx-validation should check that:
+/// - the consensus_pubkey is the same as the current one (it can't be changed)
+/// - the associated staked state is a council node and not jailed
+/// - the witness is correct
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(not(feature = "mesalock_sgx"), derive(Serialize, Deserialize))]
+pub struct EditValidatorTx {
+    /// the expected nonce on the corresponding state
+    pub nonce: Nonce,
+    /// the expected address on the corresponding state
+    pub address: StakedStateAddress,
+    /// the versioning and network identifier
+    pub attributes: StakedStateOpAttributes,
+    /// new name of the council node
+    pub name: NodeName,
+    /// new security contact of the council node
+    pub security_contact: NodeSecurityContact,
+    /// the current consensus pubkey of the council node
+    pub consensus_pubkey: TendermintValidatorPubKey,
+    /// new address allowed to sign `UnjailTx` on behalf of the council node, `None` to revoke
+    pub authorized_delegate: Option<StakedStateAddress>,
+}
+
+impl Decode for EditValidatorTx {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+        let nonce = Nonce::decode(input)?;
+        let address = StakedStateAddress::decode(input)?;
+        let attributes = StakedStateOpAttributes::decode(input)?;
+        let (name, security_contact) = decode_name_security_contact(input)?;
+        let consensus_pubkey = TendermintValidatorPubKey::decode(input)?;
+        let authorized_delegate = Option::<StakedStateAddress>::decode(input)?;
+
+        Ok(EditValidatorTx {
+            nonce,
+            address,
+            attributes,
+            name,
+            security_contact,
+            consensus_pubkey,
+            authorized_delegate,
+        })
+    }
+}
+
+impl Encode for EditValidatorTx {
+    fn encode_to<EncOut: Output>(&self, dest: &mut EncOut) {
+        dest.push(&self.nonce);
+        dest.push(&self.address);
+        dest.push(&self.attributes);
+        dest.push(&self.name);
+        dest.push(&self.security_contact);
+        dest.push(&self.consensus_pubkey);
+        dest.push(&self.authorized_delegate);
+    }
+
+    fn size_hint(&self) -> usize {
+        self.nonce.size_hint()
+            + self.address.size_hint()
+            + self.attributes.size_hint()
+            + self.name.size_hint()
+            + self.security_contact.size_hint()
+            + self.consensus_pubkey.size_hint()
+            + self.authorized_delegate.size_hint()
+    }
+}
+
+impl TransactionId for EditValidatorTx {}
+
+#[cfg(not(feature = "mesalock_sgx"))]
+impl fmt::Display for EditValidatorTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "edit validator: {} (nonce: {}) name: {}, security contact: {}",
+            self.address,
+            self.nonce,
+            self.name,
+            self.security_contact.as_deref().unwrap_or("none")
+        )?;
+        if let Some(delegate) = &self.authorized_delegate {
+            writeln!(f, "authorized delegate: {}", delegate)?;
+        }
+        write!(f, "")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::init::address::RedeemAddress;
+
+    #[test]
+    fn check_encode_decode() {
+        let tx = EditValidatorTx {
+            nonce: 1,
+            address: StakedStateAddress::BasicRedeem(RedeemAddress([0xaa; 20])),
+            attributes: StakedStateOpAttributes::new(0xab),
+            name: "council".to_owned(),
+            security_contact: Some("security@example.com".to_owned()),
+            consensus_pubkey: TendermintValidatorPubKey::Ed25519([0xcd; 32]),
+            authorized_delegate: Some(StakedStateAddress::BasicRedeem(RedeemAddress([0xbb; 20]))),
+        };
+        let encoded = tx.encode();
+        assert!(tx.size_hint() <= encoded.len());
+        assert_eq!(EditValidatorTx::decode(&mut encoded.as_ref()).unwrap(), tx);
+    }
+}
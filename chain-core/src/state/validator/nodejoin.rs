@@ -1,9 +1,14 @@
+#[cfg(not(feature = "mesalock_sgx"))]
+use crate::init::address::RedeemAddress;
+#[cfg(not(feature = "mesalock_sgx"))]
+use crate::state::account::StakedStateOpWitness;
 use crate::state::account::{NodeMetadata, Nonce, StakedStateAddress, StakedStateOpAttributes};
 use crate::tx::TransactionId;
 use parity_scale_codec::{Decode, Encode, Error, Input, Output};
 #[cfg(not(feature = "mesalock_sgx"))]
-use serde::{Deserialize, Serialize};
+use secp256k1::{Message, Secp256k1};
 #[cfg(not(feature = "mesalock_sgx"))]
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Submits a proposal to add a node:
@@ -20,7 +25,12 @@ use std::fmt;
 /// - the witness is correct
 ///
 /// # community node:
-/// FIXME
+/// the node's keypackage is registered, but it's not added to the validator set.
+///
+/// tx-validation should check that:
+/// - the associated staked state doesn't have node metadata yet
+/// - the bonded amount in the stake state is more than the minimal required one
+/// - the witness is correct
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[cfg_attr(not(feature = "mesalock_sgx"), derive(Serialize, Deserialize))]
 pub struct NodeJoinRequestTx {
@@ -51,7 +61,6 @@ impl Decode for NodeJoinRequestTx {
     }
 }
 
-// TODO: size hint as node_meta needs more info
 impl Encode for NodeJoinRequestTx {
     fn encode_to<EncOut: Output>(&self, dest: &mut EncOut) {
         dest.push(&self.nonce);
@@ -59,10 +68,29 @@ impl Encode for NodeJoinRequestTx {
         dest.push(&self.attributes);
         dest.push(&self.node_meta);
     }
+
+    fn size_hint(&self) -> usize {
+        self.nonce.size_hint()
+            + self.address.size_hint()
+            + self.attributes.size_hint()
+            + self.node_meta.size_hint()
+    }
 }
 
 impl TransactionId for NodeJoinRequestTx {}
 
+/// Error of `NodeJoinRequestTx::verify_witness`
+#[cfg(not(feature = "mesalock_sgx"))]
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum NodeJoinWitnessError {
+    /// the signature is invalid
+    #[error("witness verification failed: {0}")]
+    Verify(#[from] secp256k1::Error),
+    /// signed by a key which doesn't control the staking address
+    #[error("the witness doesn't match the staking address")]
+    AddressNotMatch,
+}
+
 impl NodeJoinRequestTx {
     /// returns the keypackage
     pub fn get_keypackage_payload(&self) -> &[u8] {
@@ -72,6 +100,27 @@ impl NodeJoinRequestTx {
         }
     }
 
+    /// checks that the witness is a signature over the tx id by the key controlling `address`
+    #[cfg(not(feature = "mesalock_sgx"))]
+    pub fn verify_witness(
+        &self,
+        witness: &StakedStateOpWitness,
+    ) -> Result<(), NodeJoinWitnessError> {
+        let signer = match witness {
+            StakedStateOpWitness::BasicRedeem(sig) => {
+                let secp = Secp256k1::verification_only();
+                let message = Message::from_slice(&self.id())?;
+                let pk = secp.recover(&message, sig)?;
+                secp.verify(&message, &sig.to_standard(), &pk)?;
+                StakedStateAddress::BasicRedeem(RedeemAddress::from(&pk))
+            }
+        };
+        if signer != self.address {
+            return Err(NodeJoinWitnessError::AddressNotMatch);
+        }
+        Ok(())
+    }
+
     /// constructs a new node join request transaction from the provided components
     #[inline]
     pub fn new(
@@ -89,7 +138,7 @@ impl NodeJoinRequestTx {
     }
 }
 
-#[cfg(not(feature = "mesalock_sgx"))]
+// only depends on `core::fmt`, so it is also available in the SGX build
 impl fmt::Display for NodeJoinRequestTx {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
@@ -100,3 +149,190 @@ impl fmt::Display for NodeJoinRequestTx {
         write!(f, "")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::init::address::RedeemAddress;
+    use crate::state::account::MAX_STRING_LEN;
+    use crate::state::account::{ConfidentialInit, CouncilNodeMeta, NodeCommonInfo};
+    use crate::state::tendermint::{TendermintValidatorAddress, TendermintValidatorPubKey};
+    use quickcheck::{quickcheck, Arbitrary, Gen};
+    use secp256k1::{PublicKey, SecretKey};
+
+    impl Arbitrary for NodeJoinRequestTx {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let mut raw_address = [0u8; 20];
+            g.fill_bytes(&mut raw_address);
+            let node_meta = if bool::arbitrary(g) {
+                NodeMetadata::CouncilNode(CouncilNodeMeta::arbitrary(g))
+            } else {
+                NodeMetadata::CommunityNode(NodeCommonInfo {
+                    name: String::arbitrary(g),
+                    security_contact: Option::<String>::arbitrary(g),
+                    confidential_init: ConfidentialInit {
+                        keypackage: Vec::arbitrary(g),
+                    },
+                })
+            };
+            NodeJoinRequestTx::new(
+                u64::arbitrary(g),
+                StakedStateAddress::BasicRedeem(RedeemAddress(raw_address)),
+                StakedStateOpAttributes {
+                    chain_hex_id: u8::arbitrary(g),
+                    app_version: u64::arbitrary(g),
+                },
+                node_meta,
+            )
+        }
+    }
+
+    fn has_valid_len(tx: &NodeJoinRequestTx) -> bool {
+        let info = match &tx.node_meta {
+            NodeMetadata::CouncilNode(cm) => &cm.node_info,
+            NodeMetadata::CommunityNode(info) => info,
+        };
+        info.name.len() <= MAX_STRING_LEN
+            && info
+                .security_contact
+                .as_ref()
+                .map_or(true, |c| c.len() <= MAX_STRING_LEN)
+    }
+
+    quickcheck! {
+        // tests if decode(encode(x)) == x
+        fn prop_encode_decode_node_join(tx: NodeJoinRequestTx) -> bool {
+            let encoded = tx.encode();
+            if has_valid_len(&tx) {
+                NodeJoinRequestTx::decode(&mut encoded.as_ref()).expect("decode node join") == tx
+            } else {
+                NodeJoinRequestTx::decode(&mut encoded.as_ref()).is_err()
+            }
+        }
+
+        // tests that a truncated buffer fails to decode rather than panic
+        fn prop_decode_truncated_node_join(tx: NodeJoinRequestTx, cut: usize) -> bool {
+            let encoded = tx.encode();
+            let mut truncated = &encoded[..cut % encoded.len()];
+            NodeJoinRequestTx::decode(&mut truncated).is_err()
+        }
+    }
+
+    fn signed_node_join_tx(
+        secret: &[u8; 32],
+        signer: &[u8; 32],
+    ) -> (NodeJoinRequestTx, StakedStateOpWitness) {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(secret).unwrap();
+        let address = StakedStateAddress::BasicRedeem(RedeemAddress::from(
+            &PublicKey::from_secret_key(&secp, &secret_key),
+        ));
+        let tx = NodeJoinRequestTx::new(
+            1,
+            address,
+            StakedStateOpAttributes::new(0xab),
+            NodeMetadata::CommunityNode(NodeCommonInfo {
+                name: "community".to_owned(),
+                security_contact: None,
+                confidential_init: confidential_init(),
+            }),
+        );
+        let message = Message::from_slice(&tx.id()).unwrap();
+        let signer_key = SecretKey::from_slice(signer).unwrap();
+        let witness = StakedStateOpWitness::new(secp.sign_recoverable(&message, &signer_key));
+        (tx, witness)
+    }
+
+    #[test]
+    fn check_verify_witness() {
+        let (tx, witness) = signed_node_join_tx(&[0xcc; 32], &[0xcc; 32]);
+        assert_eq!(tx.verify_witness(&witness), Ok(()));
+
+        // signed by another key
+        let (tx, witness) = signed_node_join_tx(&[0xcc; 32], &[0xcd; 32]);
+        assert_eq!(
+            tx.verify_witness(&witness),
+            Err(NodeJoinWitnessError::AddressNotMatch)
+        );
+
+        // signed for another tx
+        let (mut tx, witness) = signed_node_join_tx(&[0xcc; 32], &[0xcc; 32]);
+        tx.nonce += 1;
+        assert_eq!(
+            tx.verify_witness(&witness),
+            Err(NodeJoinWitnessError::AddressNotMatch)
+        );
+    }
+
+    fn node_join_tx(node_meta: NodeMetadata) -> NodeJoinRequestTx {
+        NodeJoinRequestTx::new(
+            1,
+            StakedStateAddress::BasicRedeem(RedeemAddress([0xaa; 20])),
+            StakedStateOpAttributes::new(0xab),
+            node_meta,
+        )
+    }
+
+    fn confidential_init() -> ConfidentialInit {
+        ConfidentialInit {
+            keypackage: vec![0xcc; 1024],
+        }
+    }
+
+    #[test]
+    fn check_size_hint_council_node() {
+        let tx = node_join_tx(NodeMetadata::CouncilNode(
+            CouncilNodeMeta::new_with_details(
+                "council".to_owned(),
+                Some("security@example.com".to_owned()),
+                TendermintValidatorPubKey::Ed25519([0xcd; 32]),
+                confidential_init(),
+            ),
+        ));
+        let encoded = tx.encode();
+        assert!(tx.size_hint() <= encoded.len());
+        assert!(tx.size_hint() > tx.get_keypackage_payload().len());
+    }
+
+    #[test]
+    fn check_display_council_node() {
+        let pubkey = TendermintValidatorPubKey::Ed25519([0xcd; 32]);
+        let tx = node_join_tx(NodeMetadata::CouncilNode(
+            CouncilNodeMeta::new_with_details(
+                "council".to_owned(),
+                Some("security@example.com".to_owned()),
+                pubkey.clone(),
+                confidential_init(),
+            ),
+        ));
+        let display = tx.to_string();
+        assert!(display.contains("council node (council, security contact: security@example.com"));
+        assert!(display.contains(&TendermintValidatorAddress::from(&pubkey).to_string()));
+        // only the truncated keypackage hash
+        assert!(!display.contains("cccccccc"));
+    }
+
+    #[test]
+    fn check_display_community_node() {
+        let tx = node_join_tx(NodeMetadata::CommunityNode(NodeCommonInfo {
+            name: "community".to_owned(),
+            security_contact: None,
+            confidential_init: confidential_init(),
+        }));
+        assert!(tx
+            .to_string()
+            .contains("community node (community, security contact: none, keypackage: "));
+    }
+
+    #[test]
+    fn check_size_hint_community_node() {
+        let tx = node_join_tx(NodeMetadata::CommunityNode(NodeCommonInfo {
+            name: "community".to_owned(),
+            security_contact: None,
+            confidential_init: confidential_init(),
+        }));
+        let encoded = tx.encode();
+        assert!(tx.size_hint() <= encoded.len());
+        assert!(tx.size_hint() > tx.get_keypackage_payload().len());
+    }
+}
@@ -134,3 +134,42 @@ impl Tx {
         sum_coins(self.outputs.iter().map(|x| x.value))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tx::data::address::ExtendedAddr;
+
+    fn tx_with_outputs(values: &[Coin]) -> Tx {
+        let outputs = values
+            .iter()
+            .map(|value| TxOut::new(ExtendedAddr::OrTree([0; 32]), *value))
+            .collect();
+        Tx::new_with(vec![], outputs, TxAttributes::default())
+    }
+
+    #[test]
+    fn check_output_total_empty() {
+        assert_eq!(Tx::new().get_output_total(), Ok(Coin::zero()));
+    }
+
+    #[test]
+    fn check_output_total_max_supply() {
+        let half = Coin::new(u64::from(Coin::max()) / 2).unwrap();
+        let rest = (Coin::max() - half).unwrap();
+        let tx = tx_with_outputs(&[half, rest]);
+        assert_eq!(tx.get_output_total(), Ok(Coin::max()));
+
+        let tx = tx_with_outputs(&[half, rest, Coin::zero()]);
+        assert_eq!(tx.get_output_total(), Ok(Coin::max()));
+    }
+
+    #[test]
+    fn check_output_total_exceeds_max_supply() {
+        let tx = tx_with_outputs(&[Coin::max(), Coin::new(1).unwrap()]);
+        assert!(tx.get_output_total().is_err());
+
+        let tx = tx_with_outputs(&[Coin::max(), Coin::max(), Coin::max()]);
+        assert!(tx.get_output_total().is_err());
+    }
+}
@@ -20,7 +20,7 @@ use crate::state::account::{
     WithdrawUnbondedTx,
 };
 use crate::state::tendermint::BlockHeight;
-use crate::state::validator::NodeJoinRequestTx;
+use crate::state::validator::{EditValidatorTx, NodeJoinRequestTx};
 use crate::tx::data::{txid_hash, TxId};
 use aead::Payload;
 use data::input::{TxoPointer, TxoSize};
@@ -381,7 +381,7 @@ impl TxEnclaveAux {
 }
 
 /// Transactions that are directly processed in non-enclave execution environment (chain-abci)
-/// TODO/NOTE: other TX types expected -- update of council node metadata, bonus donation, ...
+/// TODO/NOTE: other TX types expected -- bonus donation, ...
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TxPublicAux {
     /// Tx that modifies staked state -- moves some bonded stake into unbonded (witness for staked state)
@@ -390,6 +390,8 @@ pub enum TxPublicAux {
     UnjailTx(UnjailTx, StakedStateOpWitness),
     /// Tx that updates a staked state with node (community or council node) details
     NodeJoinTx(NodeJoinRequestTx, StakedStateOpWitness),
+    /// Tx that updates the metadata of a council node (witness for staked state)
+    EditValidatorTx(EditValidatorTx, StakedStateOpWitness),
 }
 
 impl Encode for TxPublicAux {
@@ -410,6 +412,11 @@ impl Encode for TxPublicAux {
                 dest.push(tx);
                 dest.push(witness);
             }
+            TxPublicAux::EditValidatorTx(ref tx, ref witness) => {
+                dest.push_byte(3);
+                dest.push(tx);
+                dest.push(witness);
+            }
         }
     }
 
@@ -418,6 +425,7 @@ impl Encode for TxPublicAux {
             TxPublicAux::UnbondStakeTx(tx, witness) => tx.size_hint() + witness.size_hint(),
             TxPublicAux::UnjailTx(tx, witness) => tx.size_hint() + witness.size_hint(),
             TxPublicAux::NodeJoinTx(tx, witness) => tx.size_hint() + witness.size_hint(),
+            TxPublicAux::EditValidatorTx(tx, witness) => tx.size_hint() + witness.size_hint(),
         }
     }
 }
@@ -425,7 +433,7 @@ impl Encode for TxPublicAux {
 impl Decode for TxPublicAux {
     fn decode<DecIn: Input>(input: &mut DecIn) -> Result<Self, Error> {
         let tag = input.read_byte()?;
-        // note: 3.. tags reserved for other tx types (node metadata update etc.)
+        // note: 4.. tags reserved for other tx types
         match tag {
             0 => {
                 let tx = UnbondTx::decode(input)?;
@@ -442,6 +450,11 @@ impl Decode for TxPublicAux {
                 let witness = StakedStateOpWitness::decode(input)?;
                 Ok(TxPublicAux::NodeJoinTx(tx, witness))
             }
+            3 => {
+                let tx = EditValidatorTx::decode(input)?;
+                let witness = StakedStateOpWitness::decode(input)?;
+                Ok(TxPublicAux::EditValidatorTx(tx, witness))
+            }
             _ => Err("No such variant in enum TxPublicAux".into()),
         }
     }
@@ -454,6 +467,7 @@ impl TxPublicAux {
             TxPublicAux::UnbondStakeTx(tx, _) => tx.id(),
             TxPublicAux::UnjailTx(tx, _) => tx.id(),
             TxPublicAux::NodeJoinTx(tx, _) => tx.id(),
+            TxPublicAux::EditValidatorTx(tx, _) => tx.id(),
         }
     }
 
@@ -463,6 +477,7 @@ impl TxPublicAux {
             TxPublicAux::UnbondStakeTx(tx, _) => &tx.attributes,
             TxPublicAux::UnjailTx(tx, _) => &tx.attributes,
             TxPublicAux::NodeJoinTx(tx, _) => &tx.attributes,
+            TxPublicAux::EditValidatorTx(tx, _) => &tx.attributes,
         }
     }
 
@@ -606,6 +621,9 @@ impl fmt::Display for TxAux {
             TxAux::PublicTx(TxPublicAux::NodeJoinTx(tx, witness)) => {
                 display_tx_witness(f, tx, witness)
             }
+            TxAux::PublicTx(TxPublicAux::EditValidatorTx(tx, witness)) => {
+                display_tx_witness(f, tx, witness)
+            }
             TxAux::MLSHandshake(_) => {
                 // FIXME
                 writeln!(f, "mls handshake")
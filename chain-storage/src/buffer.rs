@@ -28,7 +28,7 @@
 //! `(u32, Vec<u8>)`, to keep the interface consistent with merkle trie.
 //!
 //! In the case of merkle trie, for convinence, we further specialize the `Get`/`SimpleStore` for staking to
-//! `GetStaking`/`StoreStaking`.
+//! `GetStaking`/`StoreStaking`, and `IterStaking` for the storages which can enumerate the stakings.
 //!
 //! In the case of key-value database, the storage operation are further encapulated in
 //! `chain-storage` crate, the other code rarely use low level operations directly.
@@ -86,6 +86,17 @@ pub trait StoreStaking: SimpleStore<Key = StakedStateAddress, Value = StakedStat
 }
 impl<S> StoreStaking for S where S: SimpleStore<Key = StakedStateAddress, Value = StakedState> {}
 
+/// Specialized for staking, iterate through all the stored stakings
+pub trait IterStaking: GetStaking {
+    /// Iterate the stakings order by address.
+    ///
+    /// The ordering requires the full set of addresses in memory if the storage is not ordered by
+    /// address, e.g. the merkle trie is ordered by the hash of address, so the addresses are
+    /// collected and sorted first, then each staking is read again, the stakings are not kept in
+    /// memory.
+    fn iter_stakings(&self) -> Box<dyn Iterator<Item = StakedState> + '_>;
+}
+
 /// Generic readonly buffered storage implementation
 pub struct BufferGetter<'a, S: Get, H> {
     storage: S,
@@ -287,6 +298,14 @@ where
     }
 }
 
+impl IterStaking for MemStore<StakedStateAddress, StakedState> {
+    fn iter_stakings(&self) -> Box<dyn Iterator<Item = StakedState> + '_> {
+        let mut addresses = self.0.keys().copied().collect::<Vec<_>>();
+        addresses.sort();
+        Box::new(addresses.into_iter().map(move |addr| self.0[&addr].clone()))
+    }
+}
+
 /// Buffer used for staking storage
 pub type StakingBuffer = HashMap<StakedStateAddress, StakedState>;
 
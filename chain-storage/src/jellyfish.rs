@@ -18,7 +18,7 @@ use chain_core::state::tendermint::BlockHeight;
 
 use super::{COL_TRIE_NODE, COL_TRIE_STALED};
 use crate::buffer::{
-    BufferGetter, BufferSimpleStore, Get, GetKV, MemStore, StakingBuffer, StoreKV,
+    BufferGetter, BufferSimpleStore, Get, GetKV, IterStaking, MemStore, StakingBuffer, StoreKV,
 };
 
 pub use jellyfish_merkle::Version;
@@ -73,6 +73,33 @@ impl<'a, S: GetKV> Get for StakingGetter<'a, S> {
     }
 }
 
+impl<'a, S: GetKV> IterStaking for StakingGetter<'a, S> {
+    fn iter_stakings(&self) -> Box<dyn Iterator<Item = StakedState> + '_> {
+        // treat non exist version as empty set.
+        if self
+            .storage
+            .get(&(
+                COL_TRIE_NODE,
+                NodeKey::new_empty_path(self.version).encode().unwrap(),
+            ))
+            .is_none()
+        {
+            return Box::new(std::iter::empty());
+        }
+        // the merkle trie is ordered by the hash of address, sort the addresses only, then read the
+        // stakings again in order, trading the second read for not keeping the stakings in memory.
+        let mut addresses = iter_stakings(self.storage, self.version)
+            .map(|staking| staking.address)
+            .collect::<Vec<_>>();
+        addresses.sort();
+        Box::new(
+            addresses
+                .into_iter()
+                .map(move |addr| self.get(&addr).expect("staking exists in merkle trie")),
+        )
+    }
+}
+
 /// Specialized for staking
 pub type StakingBufferStore<'a, S, H> = BufferSimpleStore<'a, StakingGetter<'a, S>, H>;
 /// Specialized for staking
@@ -373,6 +400,23 @@ mod tests {
 
         check_proof(&mut app, &stakings, &root_hashes, &staking0);
 
+        // iterate current version order by address
+        let mut expected = stakings.clone();
+        expected[0] = staking0.clone();
+        assert_eq!(
+            StakingGetter::new(&app.storage, app.version - 1)
+                .iter_stakings()
+                .collect::<Vec<_>>(),
+            expected
+        );
+        // non exist version
+        assert_eq!(
+            StakingGetter::new(&app.storage, app.version)
+                .iter_stakings()
+                .count(),
+            0
+        );
+
         assert_eq!(
             sum_staking_coins(&app.storage, app.version - 1),
             Ok(Coin::new(20_0000_0000).unwrap())
@@ -324,7 +324,7 @@ pub fn verify_unbonded_withdraw_core(
     extra_info: &ChainInfo,
     account: &StakedState,
 ) -> Result<Fee, Error> {
-    verify_unjailed(account)?;
+    verify_unbondable(account)?;
 
     check_attributes(
         maintx.attributes.chain_hex_id,
@@ -376,3 +376,13 @@ pub fn verify_unjailed(account: &StakedState) -> Result<(), Error> {
         Ok(())
     }
 }
+
+/// Verifies if the account can unbond and withdraw, i.e. it's unjailed, or jailed permanently,
+/// whose coins are released after slashing since it's never unjailed
+pub fn verify_unbondable(account: &StakedState) -> Result<(), Error> {
+    if account.is_jailed() && !account.is_permanently_jailed() {
+        Err(Error::AccountJailed)
+    } else {
+        Ok(())
+    }
+}
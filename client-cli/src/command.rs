@@ -453,7 +453,7 @@ impl Command {
                     staked_state
                         .node_meta
                         .and_then(|val| match val {
-                            NodeState::CommunityNode(_) => unreachable!("FIXME"),
+                            NodeState::CommunityNode(_) => None,
                             NodeState::CouncilNode(v) => v.jailed_until,
                         })
                         .map_or_else(
@@ -15,7 +15,9 @@ use chain_core::tx::data::output::TxOut;
 use chain_core::tx::fee::FeeAlgorithm;
 use chain_core::tx::{TxAux, TxPublicAux};
 use chain_storage::jellyfish::SparseMerkleProof;
-use chain_tx_validation::{check_inputs_basic, check_outputs_basic, verify_unjailed};
+use chain_tx_validation::{
+    check_inputs_basic, check_outputs_basic, verify_unbondable, verify_unjailed,
+};
 use client_common::tendermint::types::{AbciQueryExt, Genesis, StatusResponse};
 use client_common::tendermint::Client;
 use client_common::{
@@ -196,7 +198,7 @@ where
     ) -> Result<TxAux> {
         let staked_state = self.get_staked_state(name, &address, verify_staking)?;
 
-        verify_unjailed(&staked_state).map_err(|e| {
+        verify_unbondable(&staked_state).map_err(|e| {
             Error::new(
                 ErrorKind::ValidationError,
                 format!("Failed to validate staking account: {}", e),
@@ -268,7 +270,7 @@ where
             ));
         }
 
-        verify_unjailed(&staked_state).map_err(|e| {
+        verify_unbondable(&staked_state).map_err(|e| {
             Error::new(
                 ErrorKind::ValidationError,
                 format!("Failed to validate staking account: {}", e),
@@ -377,7 +379,7 @@ where
     ) -> Result<(TxAux, TransactionPending)> {
         let staked_state = self.get_staked_state(name, from_address, verify_staking)?;
 
-        verify_unjailed(&staked_state).map_err(|e| {
+        verify_unbondable(&staked_state).map_err(|e| {
             Error::new(
                 ErrorKind::ValidationError,
                 format!("Failed to validate staking account: {}", e),
@@ -543,7 +545,7 @@ mod tests {
 
     use chain_core::init::address::RedeemAddress;
     use chain_core::init::coin::CoinError;
-    use chain_core::state::account::{StakedState, StakedStateOpAttributes, Validator};
+    use chain_core::state::account::{JailReason, StakedState, StakedStateOpAttributes, Validator};
     use chain_core::state::tendermint::BlockHeight;
     use chain_core::state::tendermint::TendermintValidatorPubKey;
     use chain_core::state::ChainState;
@@ -683,6 +685,12 @@ mod tests {
                         mock_confidential_init(),
                     ),
                     jailed_until: Some(100),
+                    jail_reason: Some(JailReason::NonLive),
+                    consecutive_jail_count: 1,
+                    last_jailed_until: Some(100),
+                    last_unjailed_at: None,
+                    authorized_delegate: None,
+                    min_self_bonded: Coin::zero(),
                     inactive_time: Some(0),
                     inactive_block: Some(BlockHeight::genesis()),
                     used_validator_addresses: vec![],